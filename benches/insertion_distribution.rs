@@ -0,0 +1,74 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use macroquad::math::{Rect, Vec2};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use qtdemo::quadtree::QuadNode;
+use qtdemo::{QUADTREE_MAX_DEPTH, QUADTREE_REGION_LIMIT, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+const POINTS: usize = 10_000;
+const CLUSTERS: usize = 5;
+const CLUSTER_SPREAD: f32 = 10.0;
+
+fn region() -> Rect {
+    Rect::new(0.0, 0.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32)
+}
+
+// Keeps samples strictly inside the half open root region so none of them get dropped
+fn clamp_to_region(position: Vec2) -> Vec2 {
+    position.clamp(Vec2::ZERO, Vec2::new(WINDOW_WIDTH as f32 - 1.0, WINDOW_HEIGHT as f32 - 1.0))
+}
+
+fn uniform(rng: &mut StdRng) -> Vec<Vec2> {
+    (0..POINTS)
+        .map(|_| Vec2::new(rng.gen_range(0.0..WINDOW_WIDTH as f32), rng.gen_range(0.0..WINDOW_HEIGHT as f32)))
+        .collect()
+}
+
+// Box-Muller normal samples around a few random centers
+fn gaussian_clusters(rng: &mut StdRng) -> Vec<Vec2> {
+    let centers = uniform(rng).into_iter().take(CLUSTERS).collect::<Vec<_>>();
+
+    (0..POINTS)
+        .map(|i| {
+            let radius = (-2.0 * (1.0 - rng.gen::<f32>()).ln()).sqrt() * CLUSTER_SPREAD;
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            clamp_to_region(centers[i % CLUSTERS] + Vec2::new(angle.cos(), angle.sin()) * radius)
+        })
+        .collect()
+}
+
+fn coincident() -> Vec<Vec2> {
+    vec![Vec2::new(WINDOW_WIDTH as f32 / 3.0, WINDOW_HEIGHT as f32 / 3.0); POINTS]
+}
+
+fn build(positions: &[Vec2]) -> QuadNode {
+    let mut tree = QuadNode::with_root(region(), QUADTREE_REGION_LIMIT).with_max_depth(QUADTREE_MAX_DEPTH);
+
+    for (i, position) in positions.iter().enumerate() {
+        tree.add(i as u32, position);
+    }
+
+    tree
+}
+
+fn insertion_distribution(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let distributions = [
+        ("uniform", uniform(&mut rng)),
+        ("gaussian_clusters", gaussian_clusters(&mut rng)),
+        ("coincident", coincident()),
+    ];
+
+    for (name, positions) in &distributions {
+        assert_eq!(build(positions).count(), POINTS, "{} dropped inserts", name);
+    }
+
+    let mut group = c.benchmark_group("insertion_distribution");
+    for (name, positions) in &distributions {
+        group.bench_function(*name, |b| b.iter(|| build(positions)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insertion_distribution);
+criterion_main!(benches);
@@ -0,0 +1,68 @@
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use macroquad::math::{Rect, Vec2};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use qtdemo::quadtree::QuadNode;
+use qtdemo::{QUADTREE_REGION_LIMIT, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+const BULLETS: usize = 5000;
+const MOVED_FRACTION: f64 = 0.1;
+
+fn region() -> Rect {
+    Rect::new(0.0, 0.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32)
+}
+
+fn build(positions: &[Vec2]) -> QuadNode {
+    let mut tree = QuadNode::with_root(region(), QUADTREE_REGION_LIMIT);
+
+    for (i, position) in positions.iter().enumerate() {
+        tree.add(i as u32, position);
+    }
+
+    tree
+}
+
+fn update(tree: &mut QuadNode, old: &[Vec2], new: &[Vec2], moved: &[usize]) {
+    for &i in moved {
+        tree.update_position(i as u32, &old[i], &new[i]);
+    }
+}
+
+fn sorted_query(tree: &QuadNode, area: &Rect) -> Vec<u32> {
+    let mut ids: Vec<u32> = tree.query(area).iter().map(|p| p.0).collect();
+    ids.sort_unstable();
+    ids
+}
+
+fn rebuild_vs_update(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let old: Vec<Vec2> = (0..BULLETS)
+        .map(|_| Vec2::new(rng.gen_range(0.0..WINDOW_WIDTH as f32), rng.gen_range(0.0..WINDOW_HEIGHT as f32)))
+        .collect();
+
+    let mut new = old.clone();
+    let moved: Vec<usize> = (0..BULLETS).filter(|_| rng.gen_bool(MOVED_FRACTION)).collect();
+    for &i in &moved {
+        let offset = Vec2::new(rng.gen_range(-5.0..5.0), rng.gen_range(-5.0..5.0));
+        new[i] = (new[i] + offset).clamp(Vec2::ZERO, Vec2::new(WINDOW_WIDTH as f32 - 1.0, WINDOW_HEIGHT as f32 - 1.0));
+    }
+
+    let base = build(&old);
+    let rebuilt = build(&new);
+    let mut updated = base.clone();
+    update(&mut updated, &old, &new, &moved);
+
+    for area in [region(), Rect::new(100.0, 100.0, 200.0, 150.0), Rect::new(450.0, 250.0, 100.0, 100.0)] {
+        assert_eq!(sorted_query(&rebuilt, &area), sorted_query(&updated, &area));
+    }
+
+    let mut group = c.benchmark_group("rebuild_vs_update");
+    group.bench_function("rebuild", |b| b.iter(|| build(&new)));
+    group.bench_function("update_position", |b| {
+        b.iter_batched(|| base.clone(), |mut tree| update(&mut tree, &old, &new, &moved), BatchSize::SmallInput)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, rebuild_vs_update);
+criterion_main!(benches);
@@ -0,0 +1,118 @@
+use std::str::FromStr;
+
+use crate::{BULLET_RADIUS, BULLET_SPAWN_ITER, BULLET_SPAWN_JITTER};
+
+// How the demo loop is paced. macroquad 0.3 picks the swap interval itself at window creation,
+// so `Vsync` leaves pacing to the swap chain and `Uncapped` is paced by the `FrameLimiter`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PresentMode {
+    #[default]
+    Vsync,
+    Uncapped,
+}
+
+impl FromStr for PresentMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "vsync" => Ok(PresentMode::Vsync),
+            "uncapped" => Ok(PresentMode::Uncapped),
+            _ => Err(format!("unknown present mode {}", value)),
+        }
+    }
+}
+
+// Startup options, read from the command line by `main`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub seed: u64,
+    pub initial_bullets: usize,
+    pub min_spacing: f32,
+    // Bullets allowed in the scene at once, spawning backs off at the cap, 0 means no cap
+    pub max_bullets: usize,
+    // Inclusive bounds every spawn burst size is drawn from
+    pub spawn_count_range: (i32, i32),
+    // Bounds every spawned bullet radius is drawn from, the upper one exclusive unless both are equal
+    pub radius_range: (f32, f32),
+    pub present_mode: PresentMode,
+    // Steps the world this many frames without a window and prints the stats, 0 runs the demo
+    pub headless_frames: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            initial_bullets: 0,
+            min_spacing: 0.0,
+            max_bullets: 0,
+            spawn_count_range: (BULLET_SPAWN_ITER - BULLET_SPAWN_JITTER, BULLET_SPAWN_ITER + BULLET_SPAWN_JITTER),
+            radius_range: (BULLET_RADIUS, BULLET_RADIUS),
+            present_mode: PresentMode::default(),
+            headless_frames: 0,
+        }
+    }
+}
+
+impl Config {
+    // Accepts `--seed <u64>`, `--initial-bullets <usize>`, `--min-spacing <f32>`, `--max-bullets <usize>`,
+    // `--spawn-count <min>..<max>`, `--radius <min>..<max>`, `--present-mode <vsync|uncapped>` and
+    // `--headless <u32>`, anything else is an error
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+
+            match arg.as_str() {
+                "--seed" => config.seed = parse_value(&arg, &value)?,
+                "--initial-bullets" => config.initial_bullets = parse_value(&arg, &value)?,
+                "--min-spacing" => config.min_spacing = parse_value(&arg, &value)?,
+                "--max-bullets" => config.max_bullets = parse_value(&arg, &value)?,
+                "--spawn-count" => config.spawn_count_range = parse_range(&arg, &value)?,
+                "--radius" => config.radius_range = parse_range(&arg, &value)?,
+                "--present-mode" => config.present_mode = parse_value(&arg, &value)?,
+                "--headless" => config.headless_frames = parse_value(&arg, &value)?,
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_value<T: FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value for {}: {}", arg, value))
+}
+
+// `<min>..<max>`, both inclusive, non negative and in order
+fn parse_range<T: FromStr + PartialOrd + Default>(arg: &str, value: &str) -> Result<(T, T), String> {
+    let (min, max) = value.split_once("..").ok_or_else(|| format!("invalid value for {}: {}", arg, value))?;
+    let range: (T, T) = (parse_value(arg, min)?, parse_value(arg, max)?);
+
+    if range.0 < T::default() || range.0 > range.1 {
+        return Err(format!("invalid value for {}: {}", arg, value));
+    }
+
+    Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn present_mode_parsing() {
+        assert_eq!(Config::from_args(args(&[])).unwrap().present_mode, PresentMode::Vsync);
+        assert_eq!(Config::from_args(args(&["--present-mode", "vsync"])).unwrap().present_mode, PresentMode::Vsync);
+        assert_eq!(Config::from_args(args(&["--present-mode", "uncapped"])).unwrap().present_mode, PresentMode::Uncapped);
+        assert!(Config::from_args(args(&["--present-mode", "adaptive"])).is_err());
+        assert!(Config::from_args(args(&["--present-mode"])).is_err());
+    }
+}
@@ -0,0 +1,828 @@
+use std::f32::consts::PI;
+
+use macroquad::math::{Circle, Rect, Vec2};
+
+use crate::{
+    BOUNCE_DECAY, BOUNCE_IMPULSE, BULLET_MAX_SPEED, CONTACT_EPSILON, DASH_MULTIPLIER, DASH_DURATION, DASH_COOLDOWN, ENTITY_RESTITUTION,
+    FRAGMENT_LIFETIME, FRAGMENT_RADIUS_SCALE, FRAGMENT_SPEED, PLAYER_ACCEL, PLAYER_FRICTION, PLAYER_VELOCITY,
+    SEPARATION_ITERATIONS, SEPARATION_SLOP, WALL_RESTITUTION,
+};
+
+pub trait Collidable {
+    fn bounding_box(&self) -> Circle;
+}
+
+pub trait Movable {
+    fn move_by(&mut self, offset: Vec2, tpf: f32);
+    fn set_position(&mut self, position: Vec2);
+}
+
+#[derive(Copy, Clone)]
+pub struct Entity {
+    pub position: Vec2,
+    pub bouding_box: Circle,
+}
+
+impl Collidable for Entity {
+    fn bounding_box(&self) -> Circle {
+        self.bouding_box
+    }
+}
+
+impl Movable for Entity {
+    fn move_by(&mut self, offset: Vec2, tpf: f32) {
+        self.position.x += offset.x * tpf * PLAYER_VELOCITY;
+        self.position.y += offset.y * tpf * PLAYER_VELOCITY;
+
+        self.bouding_box.x = self.position.x;
+        self.bouding_box.y = self.position.y;
+    }
+
+    fn set_position(&mut self, position: Vec2) {
+        self.position = position;
+        self.bouding_box.x = position.x;
+        self.bouding_box.y = position.y;
+    }
+}
+
+// `timer` counts down the remaining dash time while active, the remaining cooldown otherwise
+pub struct Dash {
+    pub cooldown: f32,
+    pub duration: f32,
+    pub active: bool,
+    pub timer: f32,
+}
+
+impl Dash {
+    pub fn new(cooldown: f32, duration: f32) -> Self {
+        Self {
+            cooldown,
+            duration,
+            active: false,
+            timer: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, tpf: f32, requested: bool) {
+        self.timer -= tpf;
+
+        if self.active {
+            if self.timer <= 0.0 {
+                self.active = false;
+                self.timer = self.cooldown;
+            }
+        } else if requested && self.timer <= 0.0 {
+            self.active = true;
+            self.timer = self.duration;
+        }
+    }
+
+    pub fn multiplier(&self) -> f32 {
+        if self.active { DASH_MULTIPLIER } else { 1.0 }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ControlMode {
+    Mouse,
+    Keyboard,
+}
+
+pub struct Player {
+    pub entity: Entity,
+    pub dash: Dash,
+    pub control: ControlMode,
+    // Push received from bullets, only applied in keyboard mode and decayed like bullet forces
+    pub recoil: Vec2,
+    // Keyboard mode momentum, `input` is the unit direction held this frame
+    pub input: Vec2,
+    pub velocity: Vec2,
+    pub accel: f32,
+    pub friction: f32,
+}
+
+impl Player {
+    pub fn new(radius: f32, position: Vec2) -> Self {
+        Self {
+            entity: Entity {
+                position,
+                bouding_box: Circle::new(position.x, position.y, radius)
+            },
+            dash: Dash::new(DASH_COOLDOWN, DASH_DURATION),
+            control: ControlMode::Mouse,
+            recoil: Vec2::ZERO,
+            input: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+            accel: PLAYER_ACCEL,
+            friction: PLAYER_FRICTION,
+        }
+    }
+
+    // Accelerates toward `input` up to the (dashing) top speed, coasts to a stop without input
+    pub fn integrate(&mut self, tpf: f32) {
+        if self.input != Vec2::ZERO {
+            let boost = self.dash.multiplier();
+            self.velocity += self.input * self.accel * boost * tpf;
+
+            let max_speed = PLAYER_VELOCITY * boost;
+            if self.velocity.length() > max_speed {
+                self.velocity = self.velocity.normalize() * max_speed;
+            }
+        } else {
+            self.velocity = apply_friction(self.velocity, self.friction, tpf);
+        }
+
+        let position = self.entity.position + self.velocity * tpf;
+        self.entity.set_position(position);
+    }
+
+    // Resizes the bounding circle around the current position
+    pub fn set_radius(&mut self, radius: f32) {
+        self.entity.bouding_box.r = radius;
+    }
+
+    pub fn apply_recoil(&mut self, tpf: f32, physics: &Physics) {
+        self.entity.move_by(self.recoil, tpf);
+        self.recoil /= physics.decay_rate;
+
+        if self.recoil.length() <= 0.01 {
+            self.recoil = Vec2::ZERO;
+        }
+    }
+
+    pub fn toggle_control(&mut self) {
+        self.control = match self.control {
+            ControlMode::Mouse => ControlMode::Keyboard,
+            ControlMode::Keyboard => ControlMode::Mouse,
+        };
+    }
+}
+
+// Tunables for the bounce response, each hit pushes with `impulse_strength` and the force is
+// divided by `decay_rate` every update. Restitution scales the reflected velocity component
+// for wall and player bounces separately, overlaps within `contact_epsilon` are ignored.
+// Bullets never move faster than `max_speed` so repeated bounces can't make them tunnel
+#[derive(Copy, Clone)]
+pub struct Physics {
+    pub impulse_strength: f32,
+    pub decay_rate: f32,
+    pub wall_restitution: f32,
+    pub entity_restitution: f32,
+    pub contact_epsilon: f32,
+    pub max_speed: f32,
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Self {
+            impulse_strength: BOUNCE_IMPULSE,
+            decay_rate: BOUNCE_DECAY,
+            wall_restitution: WALL_RESTITUTION,
+            entity_restitution: ENTITY_RESTITUTION,
+            contact_epsilon: CONTACT_EPSILON,
+            max_speed: BULLET_MAX_SPEED,
+        }
+    }
+}
+
+pub struct Bullet {
+    pub entity: Entity,
+    pub velocity: Vec2,
+    pub forces: Vec<Vec2>,
+    // Seconds left before despawning, `None` lives until it leaves the play area
+    pub lifetime: Option<f32>,
+    // Tree leaf the center was in at the last refresh, `None` until first seen inside the tree
+    pub leaf: Option<Rect>,
+}
+
+impl Bullet {
+    pub fn new(position: Vec2, radius: f32, falling_speed: f32) -> Self {
+        Self::with_velocity(position, radius, Vec2::new(0.0, falling_speed))
+    }
+
+    pub fn with_velocity(position: Vec2, radius: f32, velocity: Vec2) -> Self {
+        Self {
+            entity: Entity {
+                position,
+                bouding_box: Circle::new(position.x, position.y, radius)
+            },
+            velocity,
+            forces: Vec::new(),
+            lifetime: None,
+            leaf: None,
+        }
+    }
+
+    pub fn fall(&mut self, tpf: f32) {
+        let position = self.entity.position + self.velocity * tpf;
+        self.entity.set_position(position);
+    }
+
+    // With each update, applied forces should get smaller and smaller till they get deleted from `forces`
+    pub fn update(&mut self, tpf: f32, physics: &Physics) {
+        self.velocity = clamp_speed(self.velocity, physics.max_speed);
+        self.fall(tpf);
+        self.apply_forces(tpf, physics);
+
+        if let Some(lifetime) = &mut self.lifetime {
+            *lifetime -= tpf;
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.lifetime.is_some_and(|lifetime| lifetime <= 0.0)
+    }
+
+    // `count` smaller short lived bullets flying outward at evenly spaced angles
+    pub fn fragment(&self, count: usize) -> Vec<Bullet> {
+        let radius = self.entity.bouding_box.r * FRAGMENT_RADIUS_SCALE;
+
+        (0..count)
+            .map(|i| {
+                let angle = 2.0 * PI * i as f32 / count as f32;
+                let velocity = Vec2::new(angle.cos(), angle.sin()) * FRAGMENT_SPEED;
+
+                let mut fragment = Bullet::with_velocity(self.entity.position, radius, velocity);
+                fragment.lifetime = Some(FRAGMENT_LIFETIME);
+                fragment
+            })
+            .collect()
+    }
+
+    pub fn register_force(&mut self, direction: Vec2, physics: &Physics) {
+        let force = direction * physics.impulse_strength;
+
+        if force.length() > 0.01 {
+            self.forces.push(force);
+        }
+    }
+
+    pub fn apply_forces(&mut self, tpf: f32, physics: &Physics) {
+        let mut n_low_forces = 0;
+
+        for force in &mut self.forces {
+            self.entity.move_by(*force, tpf);
+            force.x /= physics.decay_rate;
+            force.y /= physics.decay_rate;
+
+            if force.length() <= 0.01 {
+                n_low_forces += 1;
+            }
+        }
+
+        if n_low_forces > 0 {
+            self.forces.retain(|f| f.length() > 0.01);
+        }
+    }
+}
+
+// Bullet attached to the player surface, kept `offset` away from the player center at `angle`
+// so it orbits along with every player move
+pub struct StuckBullet {
+    pub bullet: Bullet,
+    pub angle: f32,
+    pub offset: f32,
+}
+
+impl StuckBullet {
+    // Attaches where the bullet touched, resting on the player circle instead of overlapping it
+    pub fn attach(bullet: Bullet, player: &Player) -> Self {
+        let delta = bullet.entity.position - player.entity.position;
+        let offset = player.entity.bouding_box.r + bullet.entity.bouding_box.r;
+
+        Self {
+            bullet,
+            angle: delta.y.atan2(delta.x),
+            offset,
+        }
+    }
+
+    pub fn follow(&mut self, center: Vec2) {
+        let position = center + Vec2::new(self.angle.cos(), self.angle.sin()) * self.offset;
+        self.bullet.entity.set_position(position);
+    }
+}
+
+pub fn player_query_rect(player: &Player) -> Rect {
+    Rect::new(
+        player.entity.position.x - player.entity.bouding_box.r,
+        player.entity.position.y - player.entity.bouding_box.r,
+        player.entity.bouding_box.r * 2.0,
+        player.entity.bouding_box.r * 2.0,
+    )
+}
+
+// Direction pushing `bullet` away from `player`, taken from the entity positions rather
+// than the bounding circles so it can never lag behind a move
+pub fn collision_normal(bullet: &Bullet, player: &Player) -> Vec2 {
+    (bullet.entity.position - player.entity.position).normalize_or_zero()
+}
+
+// Slows `velocity` by `friction` units per second, stopping at zero instead of reversing
+pub fn apply_friction(velocity: Vec2, friction: f32, dt: f32) -> Vec2 {
+    let speed = velocity.length();
+    if speed == 0.0 {
+        return Vec2::ZERO;
+    }
+
+    velocity * ((speed - friction * dt).max(0.0) / speed)
+}
+
+// Scales `velocity` down to a length of `max`, keeping its direction, slower ones are untouched
+pub fn clamp_speed(velocity: Vec2, max: f32) -> Vec2 {
+    velocity.clamp_length_max(max)
+}
+
+// Flips the part of `velocity` heading against `normal` and scales it by `restitution`, leaves
+// velocities already moving away untouched
+pub fn reflect(velocity: Vec2, normal: Vec2, restitution: f32) -> Vec2 {
+    let approach = velocity.dot(normal);
+    if approach >= 0.0 {
+        return velocity;
+    }
+
+    velocity - normal * approach * (1.0 + restitution)
+}
+
+// Keeps `bullet` inside `bounds`, reflecting it off whichever edges it crossed. Returns whether
+// it hit a wall
+pub fn bounce_off_walls(bullet: &mut Bullet, bounds: &Rect, restitution: f32) -> bool {
+    let mut position = bullet.entity.position;
+    let mut hit = false;
+
+    if position.x < bounds.left() {
+        position.x = bounds.left();
+        bullet.velocity = reflect(bullet.velocity, Vec2::new(1.0, 0.0), restitution);
+        hit = true;
+    } else if position.x > bounds.right() {
+        position.x = bounds.right();
+        bullet.velocity = reflect(bullet.velocity, Vec2::new(-1.0, 0.0), restitution);
+        hit = true;
+    }
+
+    if position.y < bounds.top() {
+        position.y = bounds.top();
+        bullet.velocity = reflect(bullet.velocity, Vec2::new(0.0, 1.0), restitution);
+        hit = true;
+    } else if position.y > bounds.bottom() {
+        position.y = bounds.bottom();
+        bullet.velocity = reflect(bullet.velocity, Vec2::new(0.0, -1.0), restitution);
+        hit = true;
+    }
+
+    if hit {
+        bullet.entity.set_position(position);
+    }
+
+    hit
+}
+
+// Mass is taken as the circle's area so bigger entities are harder to push
+fn mass(circle: &Circle) -> f32 {
+    PI * circle.r * circle.r
+}
+
+// Pushes `bullet` away from the player and returns the opposite impulse for the player, scaled
+// by the bullet to player mass ratio
+pub fn resolve_hit(bullet: &mut Bullet, player: &Player, physics: &Physics) -> Vec2 {
+    let direction = collision_normal(bullet, player);
+    bullet.register_force(direction, physics);
+    bullet.velocity = reflect(bullet.velocity, direction, physics.entity_restitution);
+
+    let ratio = mass(&bullet.entity.bouding_box) / mass(&player.entity.bouding_box);
+    -direction * physics.impulse_strength * ratio
+}
+
+// Area of the lens shaped intersection of two circles
+pub fn circle_overlap_area(a: &Circle, b: &Circle) -> f32 {
+    let d = a.point().distance(b.point());
+
+    if d >= a.r + b.r {
+        return 0.0;
+    }
+
+    if d <= (a.r - b.r).abs() {
+        let r = a.r.min(b.r);
+        return PI * r * r;
+    }
+
+    let alpha = ((d * d + a.r * a.r - b.r * b.r) / (2.0 * d * a.r)).clamp(-1.0, 1.0).acos();
+    let beta = ((d * d + b.r * b.r - a.r * a.r) / (2.0 * d * b.r)).clamp(-1.0, 1.0).acos();
+    let kite = (-d + a.r + b.r) * (d + a.r - b.r) * (d - a.r + b.r) * (d + a.r + b.r);
+
+    a.r * a.r * alpha + b.r * b.r * beta - 0.5 * kite.max(0.0).sqrt()
+}
+
+// How deep two circles overlap, negative while they are apart
+pub fn penetration(a: &Circle, b: &Circle) -> f32 {
+    a.r + b.r - a.point().distance(b.point())
+}
+
+// Overlaps up to `contact_epsilon` deep count as resting contact rather than a hit, so bullets
+// settled against the player don't keep getting pushed
+pub fn try_hit(player: &Player, bullets: &[Bullet], possible_ids: Vec<u32>, contact_epsilon: f32) -> Option<Vec<usize>> {
+    let mut ids = Vec::new();
+
+    // Stale ids (e.g. from a tree not rebuilt after despawns) are skipped instead of panicking
+    for i in possible_ids {
+        let Some(bullet) = bullets.get(i as usize) else {
+            continue;
+        };
+
+        if penetration(&bullet.entity.bouding_box, &player.entity.bouding_box) > contact_epsilon {
+            ids.push(i as usize);
+        }
+    }
+
+    if !ids.is_empty() {
+        return Some(ids);
+    }
+
+    None
+}
+
+// Iteratively resolves penetration against every hit bullet, so pushing out of one
+// bullet into another still ends up clear of both
+pub fn separation_vector(player: &Player, hits: &[usize], bullets: &[Bullet]) -> Vec2 {
+    let mut offset = Vec2::ZERO;
+
+    for _ in 0..SEPARATION_ITERATIONS {
+        let mut resolved = true;
+
+        for &hit in hits {
+            let bullet = &bullets[hit].entity.bouding_box;
+            let player_pos = player.entity.position + offset;
+            let delta = player_pos - bullet.point();
+            let distance = delta.length();
+            let penetration = player.entity.bouding_box.r + bullet.r - distance;
+
+            if penetration > 0.0 {
+                let normal = if distance > 0.0 { delta / distance } else { Vec2::new(0.0, -1.0) };
+                offset += normal * (penetration + SEPARATION_SLOP);
+                resolved = false;
+            }
+        }
+
+        if resolved {
+            break;
+        }
+    }
+
+    offset
+}
+
+// Pull from `from` toward `to`, fading linearly to zero at `radius`
+pub fn attraction(from: Vec2, to: Vec2, strength: f32, radius: f32) -> Vec2 {
+    let delta = to - from;
+    let distance = delta.length();
+
+    if distance == 0.0 || distance >= radius {
+        return Vec2::ZERO;
+    }
+
+    delta / distance * strength * (1.0 - distance / radius)
+}
+
+// Earliest time at which `bullet`, moving at its velocity, touches the stationary player
+pub fn time_to_impact(bullet: &Bullet, player: &Player) -> Option<f32> {
+    let offset = bullet.entity.position - player.entity.position;
+    let radii = bullet.entity.bouding_box.r + player.entity.bouding_box.r;
+
+    let a = bullet.velocity.dot(bullet.velocity);
+    let b = 2.0 * offset.dot(bullet.velocity);
+    let c = offset.dot(offset) - radii * radii;
+
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / (2.0 * a);
+    if t < 0.0 {
+        return None;
+    }
+
+    Some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dash_boost_ends_after_duration() {
+        let mut dash = Dash::new(1.0, 0.2);
+        dash.update(0.0, true);
+        assert!(dash.active);
+        assert_eq!(dash.multiplier(), DASH_MULTIPLIER);
+
+        dash.update(0.1, false);
+        assert!(dash.active);
+        dash.update(0.15, false);
+        assert!(!dash.active);
+        assert_eq!(dash.multiplier(), 1.0);
+    }
+
+    #[test]
+    fn dash_blocked_during_cooldown() {
+        let mut dash = Dash::new(1.0, 0.2);
+        dash.update(0.0, true);
+        dash.update(0.25, false);
+        assert!(!dash.active);
+
+        // Requests only go through once the whole cooldown has passed
+        dash.update(0.5, true);
+        assert!(!dash.active);
+        dash.update(0.4, true);
+        assert!(!dash.active);
+        dash.update(0.2, true);
+        assert!(dash.active);
+    }
+
+    #[test]
+    fn clamp_speed_keeps_slower_velocities() {
+        let velocity = Vec2::new(3.0, -4.0);
+        assert_eq!(clamp_speed(velocity, 5.0), velocity);
+        assert_eq!(clamp_speed(velocity, 10.0), velocity);
+        assert_eq!(clamp_speed(Vec2::ZERO, 1.0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn clamp_speed_scales_faster_velocities_to_max() {
+        let clamped = clamp_speed(Vec2::new(30.0, -40.0), 5.0);
+        assert!((clamped.length() - 5.0).abs() < 1e-5);
+        assert!((clamped - Vec2::new(3.0, -4.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn attraction_points_toward_target() {
+        let pull = attraction(Vec2::new(10.0, 10.0), Vec2::new(10.0, 30.0), 5.0, 100.0);
+        assert_eq!(pull.x, 0.0);
+        assert!(pull.y > 0.0);
+
+        let pull = attraction(Vec2::new(10.0, 10.0), Vec2::new(-20.0, 10.0), 5.0, 100.0);
+        assert!(pull.x < 0.0);
+        assert_eq!(pull.y, 0.0);
+    }
+
+    #[test]
+    fn attraction_falls_off_linearly() {
+        let from = Vec2::ZERO;
+        let strength = 8.0;
+        let radius = 100.0;
+
+        assert_eq!(attraction(from, from, strength, radius), Vec2::ZERO);
+        assert!((attraction(from, Vec2::new(25.0, 0.0), strength, radius).length() - 6.0).abs() < 1e-5);
+        assert!((attraction(from, Vec2::new(50.0, 0.0), strength, radius).length() - 4.0).abs() < 1e-5);
+        assert!((attraction(from, Vec2::new(75.0, 0.0), strength, radius).length() - 2.0).abs() < 1e-5);
+        assert_eq!(attraction(from, Vec2::new(100.0, 0.0), strength, radius), Vec2::ZERO);
+        assert_eq!(attraction(from, Vec2::new(150.0, 0.0), strength, radius), Vec2::ZERO);
+    }
+
+    #[test]
+    fn separation_clears_several_bullets() {
+        let player = Player::new(20.0, Vec2::new(100.0, 100.0));
+        let bullets = vec![
+            Bullet::with_velocity(Vec2::new(115.0, 100.0), 5.0, Vec2::ZERO),
+            Bullet::with_velocity(Vec2::new(100.0, 115.0), 5.0, Vec2::ZERO),
+            Bullet::with_velocity(Vec2::new(108.0, 108.0), 3.0, Vec2::ZERO),
+        ];
+
+        let offset = separation_vector(&player, &[0, 1, 2], &bullets);
+        let resolved = player.entity.position + offset;
+
+        for bullet in &bullets {
+            let gap = resolved.distance(bullet.entity.position) - player.entity.bouding_box.r - bullet.entity.bouding_box.r;
+            assert!(gap >= 0.0, "still overlapping by {}", -gap);
+        }
+        assert!(offset.x < 0.0 && offset.y < 0.0);
+    }
+
+    #[test]
+    fn set_radius_resizes_query_rect() {
+        let mut player = Player::new(20.0, Vec2::new(100.0, 50.0));
+        assert_eq!(player_query_rect(&player), Rect::new(80.0, 30.0, 40.0, 40.0));
+
+        player.set_radius(35.0);
+        assert_eq!(player.entity.bouding_box.r, 35.0);
+        assert_eq!(player.entity.position, Vec2::new(100.0, 50.0));
+        assert_eq!(player_query_rect(&player), Rect::new(65.0, 15.0, 70.0, 70.0));
+    }
+
+    #[test]
+    fn bounding_circle_follows_move_by() {
+        let mut player = Player::new(20.0, Vec2::new(100.0, 100.0));
+        let mut bullet = Bullet::with_velocity(Vec2::new(130.0, 100.0), 2.0, Vec2::ZERO);
+
+        player.entity.move_by(Vec2::new(0.5, -0.25), 0.1);
+        bullet.entity.move_by(Vec2::new(-0.1, 0.3), 0.1);
+        assert_eq!(player.entity.bouding_box.point(), player.entity.position);
+        assert_eq!(bullet.entity.bouding_box.point(), bullet.entity.position);
+
+        let expected = (bullet.entity.position - player.entity.position).normalize();
+        assert_eq!(collision_normal(&bullet, &player), expected);
+    }
+
+    #[test]
+    fn time_to_impact_head_on() {
+        let player = Player::new(9.0, Vec2::ZERO);
+        let bullet = Bullet::with_velocity(Vec2::new(200.0, 0.0), 1.0, Vec2::new(-100.0, 0.0));
+
+        let toi = time_to_impact(&bullet, &player).unwrap();
+        assert!((toi - 1.9).abs() < 1e-5);
+    }
+
+    #[test]
+    fn time_to_impact_receding() {
+        let player = Player::new(9.0, Vec2::ZERO);
+        let bullet = Bullet::with_velocity(Vec2::new(200.0, 0.0), 1.0, Vec2::new(100.0, 0.0));
+        assert_eq!(time_to_impact(&bullet, &player), None);
+
+        let still = Bullet::with_velocity(Vec2::new(200.0, 0.0), 1.0, Vec2::ZERO);
+        assert_eq!(time_to_impact(&still, &player), None);
+    }
+
+    #[test]
+    fn time_to_impact_grazing() {
+        let player = Player::new(9.0, Vec2::ZERO);
+
+        // Passes 9.5 from the center with radii summing to 10, touching 3.12 short of the closest point
+        let grazing = Bullet::with_velocity(Vec2::new(200.0, 9.5), 1.0, Vec2::new(-100.0, 0.0));
+        let toi = time_to_impact(&grazing, &player).unwrap();
+        assert!((toi - (200.0 - 9.75f32.sqrt()) / 100.0).abs() < 1e-5);
+
+        let missing = Bullet::with_velocity(Vec2::new(200.0, 10.5), 1.0, Vec2::new(-100.0, 0.0));
+        assert_eq!(time_to_impact(&missing, &player), None);
+    }
+
+    #[test]
+    fn overlap_area_disjoint() {
+        assert_eq!(circle_overlap_area(&Circle::new(0.0, 0.0, 1.0), &Circle::new(3.0, 0.0, 1.0)), 0.0);
+        assert_eq!(circle_overlap_area(&Circle::new(0.0, 0.0, 1.0), &Circle::new(2.0, 0.0, 1.0)), 0.0);
+    }
+
+    #[test]
+    fn overlap_area_contained() {
+        let area = circle_overlap_area(&Circle::new(0.0, 0.0, 10.0), &Circle::new(2.0, 1.0, 3.0));
+        assert!((area - PI * 9.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn overlap_area_partial() {
+        // Two unit circles one radius apart share a lens of 2pi/3 - sqrt(3)/2
+        let area = circle_overlap_area(&Circle::new(0.0, 0.0, 1.0), &Circle::new(1.0, 0.0, 1.0));
+        assert!((area - (2.0 * PI / 3.0 - 3.0f32.sqrt() / 2.0)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn double_impulse_doubles_displacement() {
+        let displacement = |impulse_strength: f32| {
+            let physics = Physics { impulse_strength, ..Physics::default() };
+            let mut bullet = Bullet::with_velocity(Vec2::new(100.0, 100.0), 1.0, Vec2::ZERO);
+            bullet.register_force(Vec2::new(0.6, -0.8), &physics);
+            bullet.update(1.0 / 60.0, &physics);
+            bullet.entity.position - Vec2::new(100.0, 100.0)
+        };
+
+        let single = displacement(1.5);
+        let double = displacement(3.0);
+        assert!(single.length() > 0.0);
+        assert!((double - single * 2.0).length() < 1e-4);
+    }
+
+    #[test]
+    fn try_hit_skips_out_of_range_ids() {
+        let player = Player::new(10.0, Vec2::new(50.0, 50.0));
+        let bullets = vec![
+            Bullet::with_velocity(Vec2::new(55.0, 50.0), 2.0, Vec2::ZERO),
+            Bullet::with_velocity(Vec2::new(90.0, 90.0), 2.0, Vec2::ZERO),
+        ];
+
+        assert_eq!(try_hit(&player, &bullets, vec![0, 1, 2, 40], 0.0), Some(vec![0]));
+        assert_eq!(try_hit(&player, &bullets, vec![7], 0.0), None);
+    }
+
+    #[test]
+    fn resolve_hit_recoil_scaled_by_mass_ratio() {
+        let physics = Physics::default();
+        let player = Player::new(20.0, Vec2::new(100.0, 100.0));
+
+        let mut small = Bullet::with_velocity(Vec2::new(115.0, 100.0), 2.0, Vec2::ZERO);
+        let mut large = Bullet::with_velocity(Vec2::new(115.0, 100.0), 4.0, Vec2::ZERO);
+        let small_recoil = resolve_hit(&mut small, &player, &physics);
+        let large_recoil = resolve_hit(&mut large, &player, &physics);
+
+        // Opposite to the push on the bullet, and four times the mass gives four times the recoil
+        assert_eq!(small.forces, vec![Vec2::new(physics.impulse_strength, 0.0)]);
+        assert!(small_recoil.x < 0.0 && small_recoil.y == 0.0);
+        assert!((small_recoil.x - -physics.impulse_strength * 4.0 / 400.0).abs() < 1e-6);
+        assert!((large_recoil - small_recoil * 4.0).length() < 1e-6);
+    }
+
+    #[test]
+    fn fragment_spacing_and_radius() {
+        let bullet = Bullet::with_velocity(Vec2::new(10.0, 20.0), 4.0, Vec2::new(50.0, 0.0));
+        let fragments = bullet.fragment(4);
+        assert_eq!(fragments.len(), 4);
+
+        let expected = [Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0), Vec2::new(-1.0, 0.0), Vec2::new(0.0, -1.0)];
+        for (fragment, direction) in fragments.iter().zip(expected) {
+            assert_eq!(fragment.entity.position, bullet.entity.position);
+            assert_eq!(fragment.entity.bouding_box.r, 4.0 * FRAGMENT_RADIUS_SCALE);
+            assert_eq!(fragment.lifetime, Some(FRAGMENT_LIFETIME));
+            assert!((fragment.velocity - direction * FRAGMENT_SPEED).length() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn friction_decays_without_overshoot() {
+        let velocity = apply_friction(Vec2::new(300.0, 400.0), 100.0, 0.5);
+        assert!((velocity - Vec2::new(270.0, 360.0)).length() < 1e-3);
+
+        // More friction than speed stops dead instead of reversing
+        assert_eq!(apply_friction(Vec2::new(3.0, 4.0), 100.0, 0.5), Vec2::ZERO);
+        assert_eq!(apply_friction(Vec2::ZERO, 100.0, 0.5), Vec2::ZERO);
+
+        let mut velocity = Vec2::new(-120.0, 0.0);
+        for _ in 0..100 {
+            velocity = apply_friction(velocity, 100.0, 0.05);
+            assert!(velocity.x <= 0.0 && velocity.y == 0.0);
+        }
+        assert_eq!(velocity, Vec2::ZERO);
+    }
+
+    #[test]
+    fn reflect_scales_normal_part_by_restitution() {
+        let normal = Vec2::new(0.0, -1.0);
+        assert_eq!(reflect(Vec2::new(3.0, 4.0), normal, 1.0), Vec2::new(3.0, -4.0));
+        assert_eq!(reflect(Vec2::new(3.0, 4.0), normal, 0.5), Vec2::new(3.0, -2.0));
+        assert_eq!(reflect(Vec2::new(3.0, -4.0), normal, 0.5), Vec2::new(3.0, -4.0));
+    }
+
+    #[test]
+    fn wall_bounce_uses_wall_restitution() {
+        let bounds = Rect::new(0.0, 0.0, 100.0, 100.0);
+
+        let mut bullet = Bullet::with_velocity(Vec2::new(105.0, 50.0), 1.0, Vec2::new(40.0, 10.0));
+        assert!(bounce_off_walls(&mut bullet, &bounds, 0.5));
+        assert_eq!(bullet.entity.position, Vec2::new(100.0, 50.0));
+        assert_eq!(bullet.velocity, Vec2::new(-20.0, 10.0));
+
+        // A corner flips both components
+        let mut bullet = Bullet::with_velocity(Vec2::new(-1.0, -1.0), 1.0, Vec2::new(-10.0, -20.0));
+        assert!(bounce_off_walls(&mut bullet, &bounds, 1.0));
+        assert_eq!(bullet.velocity, Vec2::new(10.0, 20.0));
+
+        let mut inside = Bullet::with_velocity(Vec2::new(50.0, 50.0), 1.0, Vec2::new(-10.0, 0.0));
+        assert!(!bounce_off_walls(&mut inside, &bounds, 1.0));
+    }
+
+    #[test]
+    fn entity_bounce_uses_entity_restitution() {
+        let player = Player::new(20.0, Vec2::new(100.0, 100.0));
+        let bounced = |entity_restitution: f32| {
+            let physics = Physics { entity_restitution, ..Physics::default() };
+            let mut bullet = Bullet::with_velocity(Vec2::new(115.0, 100.0), 2.0, Vec2::new(-80.0, 30.0));
+            resolve_hit(&mut bullet, &player, &physics);
+            bullet.velocity
+        };
+
+        assert!((bounced(1.0) - Vec2::new(80.0, 30.0)).length() < 1e-4);
+        assert!((bounced(0.25) - Vec2::new(20.0, 30.0)).length() < 1e-4);
+    }
+
+    #[test]
+    fn try_hit_ignores_overlap_below_epsilon() {
+        let player = Player::new(10.0, Vec2::ZERO);
+        let resting = vec![Bullet::with_velocity(Vec2::new(11.75, 0.0), 2.0, Vec2::ZERO)];
+        let pressing = vec![Bullet::with_velocity(Vec2::new(11.0, 0.0), 2.0, Vec2::ZERO)];
+
+        // 0.25 deep resting contact against 1.0 deep overlap, with a 0.5 epsilon
+        assert_eq!(try_hit(&player, &resting, vec![0], 0.5), None);
+        assert_eq!(try_hit(&player, &pressing, vec![0], 0.5), Some(vec![0]));
+        assert_eq!(try_hit(&player, &resting, vec![0], 0.0), Some(vec![0]));
+    }
+
+    #[test]
+    fn stuck_bullet_keeps_its_distance() {
+        let mut player = Player::new(20.0, Vec2::new(100.0, 100.0));
+        let bullet = Bullet::with_velocity(Vec2::new(100.0, 118.0), 3.0, Vec2::new(0.0, -50.0));
+        let mut stuck = StuckBullet::attach(bullet, &player);
+
+        for step in 0..20 {
+            player.entity.set_position(Vec2::new(100.0 + step as f32 * 7.0, 100.0 - step as f32 * 3.0));
+            stuck.follow(player.entity.position);
+
+            let distance = stuck.bullet.entity.position.distance(player.entity.position);
+            assert!((distance - 23.0).abs() < 1e-3);
+            assert_eq!(stuck.bullet.entity.bouding_box.point(), stuck.bullet.entity.position);
+        }
+
+        // Still on the side it touched
+        assert!(stuck.bullet.entity.position.y > player.entity.position.y);
+    }
+}
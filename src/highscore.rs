@@ -0,0 +1,77 @@
+use std::fs;
+use std::io;
+
+// Best results across runs, stored as `<score> <survival seconds>` on a single line
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct HighScore {
+    pub score: u32,
+    pub survival_time: f64,
+}
+
+impl HighScore {
+    // A missing or unreadable file counts as no high score yet
+    pub fn load(path: &str) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| Self::parse(&contents))
+            .unwrap_or_default()
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        let mut fields = contents.split_whitespace();
+        let score = fields.next()?.parse().ok()?;
+        let survival_time = fields.next()?.parse().ok()?;
+
+        Some(Self { score, survival_time })
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, format!("{} {}\n", self.score, self.survival_time))
+    }
+
+    // Keeps the better of each field, returns whether anything improved
+    pub fn record(&mut self, score: u32, survival_time: f64) -> bool {
+        let improved = score > self.score || survival_time > self.survival_time;
+
+        self.score = self.score.max(score);
+        self.survival_time = self.survival_time.max(survival_time);
+
+        improved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("qtdemo-{}-{}.txt", name, std::process::id())).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn missing_file_is_default() {
+        assert_eq!(HighScore::load(&temp_path("missing")), HighScore::default());
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let path = temp_path("round-trip");
+        let high_score = HighScore { score: 1234, survival_time: 56.75 };
+
+        high_score.save(&path).unwrap();
+        let loaded = HighScore::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, high_score);
+    }
+
+    #[test]
+    fn corrupt_file_is_default() {
+        let path = temp_path("corrupt");
+        fs::write(&path, "not a score\n").unwrap();
+        let loaded = HighScore::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, HighScore::default());
+    }
+}
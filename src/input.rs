@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+
+use macroquad::input::KeyCode;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Pause,
+    ToggleGrid,
+    Dash,
+}
+
+// Key assigned to every remappable action, `Default` is the stock layout
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeyBindings {
+    pub move_up: KeyCode,
+    pub move_down: KeyCode,
+    pub move_left: KeyCode,
+    pub move_right: KeyCode,
+    pub pause: KeyCode,
+    pub toggle_grid: KeyCode,
+    pub dash: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            move_up: KeyCode::W,
+            move_down: KeyCode::S,
+            move_left: KeyCode::A,
+            move_right: KeyCode::D,
+            pause: KeyCode::Space,
+            toggle_grid: KeyCode::R,
+            dash: KeyCode::LeftShift,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn bindings(&self) -> [(Action, KeyCode); 7] {
+        [
+            (Action::MoveUp, self.move_up),
+            (Action::MoveDown, self.move_down),
+            (Action::MoveLeft, self.move_left),
+            (Action::MoveRight, self.move_right),
+            (Action::Pause, self.pause),
+            (Action::ToggleGrid, self.toggle_grid),
+            (Action::Dash, self.dash),
+        ]
+    }
+}
+
+// Actions whose key is in `keys`, in declaration order. `main` calls it once with the held
+// keys and once with the keys pressed this frame
+pub fn active_actions(keys: &HashSet<KeyCode>, bindings: &KeyBindings) -> Vec<Action> {
+    bindings.bindings()
+        .into_iter()
+        .filter(|(_, key)| keys.contains(key))
+        .map(|(action, _)| action)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_actions_from_key_sets() {
+        let bindings = KeyBindings::default();
+
+        let keys = HashSet::from([KeyCode::D, KeyCode::W, KeyCode::Q]);
+        assert_eq!(active_actions(&keys, &bindings), vec![Action::MoveUp, Action::MoveRight]);
+        assert!(active_actions(&HashSet::new(), &bindings).is_empty());
+
+        let remapped = KeyBindings { dash: KeyCode::Q, ..bindings };
+        assert_eq!(active_actions(&keys, &remapped), vec![Action::MoveUp, Action::MoveRight, Action::Dash]);
+    }
+}
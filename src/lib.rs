@@ -0,0 +1,78 @@
+pub mod config;
+pub mod entity;
+pub mod highscore;
+pub mod input;
+pub mod quadtree;
+pub mod recording;
+pub mod render;
+pub mod scenario;
+pub mod spawner;
+pub mod stats;
+pub mod timing;
+pub mod world;
+
+pub const PLAYER_VELOCITY: f32 = 300.0;
+pub const PLAYER_ACCEL: f32 = 1500.0;
+pub const PLAYER_FRICTION: f32 = 1200.0;
+pub const PLAYER_RADIUS: f32 = 100.0;
+pub const PLAYER_RADIUS_STEP: f32 = 10.0;
+pub const PLAYER_MIN_RADIUS: f32 = 10.0;
+pub const PLAYER_MAX_RADIUS: f32 = 300.0;
+pub const DASH_MULTIPLIER: f32 = 3.0;
+pub const DASH_DURATION: f32 = 0.15;
+pub const DASH_COOLDOWN: f32 = 1.0;
+pub const SEPARATION_ITERATIONS: usize = 8;
+pub const SEPARATION_SLOP: f32 = 0.001;
+
+pub const WINDOW_WIDTH: i32 = 1000;
+pub const WINDOW_HEIGHT: i32 = 600;
+pub const FRAME_CAP_DT: f64 = 1.0 / 60.0;
+pub const ZOOM_STEP: f32 = 1.1;
+pub const MIN_ZOOM: f32 = 0.1;
+pub const MAX_ZOOM: f32 = 10.0;
+pub const LOD_THRESHOLD_PX: f32 = 4.0;
+pub const CIRCLE_MIN_SEGMENTS: u8 = 8;
+pub const CIRCLE_MAX_SEGMENTS: u8 = 128;
+pub const CIRCLE_SEGMENT_LENGTH: f32 = 4.0;
+pub const HIGHSCORE_PATH: &str = "highscore.txt";
+pub const RECORDING_DIR: &str = "frames";
+pub const RECORDING_FRAMES: u32 = 120;
+pub const REFERENCE_GRID_SPACING: f32 = 100.0;
+
+pub const QUADTREE_REGION_LIMIT: usize = 10;
+pub const QUADTREE_MIN_REGION_LIMIT: usize = 1;
+pub const QUADTREE_MAX_REGION_LIMIT: usize = 50;
+pub const QUADTREE_MAX_DEPTH: usize = 12;
+pub const QUADTREE_MIN_NODE_SIZE: f32 = 1.0;
+pub const DEGENERATE_DEPTH_RATIO: f32 = 2.0;
+pub const FIT_PADDING: f32 = 10.0;
+pub const ACTIVITY_PER_INSERT: f32 = 0.1;
+pub const ACTIVITY_DECAY_RATE: f32 = 3.0;
+pub const GRID_CELL_SIZE: f32 = 50.0;
+pub const IMPACT_PREDICTION_COUNT: usize = 10;
+pub const VELOCITY_LINE_SCALE: f32 = 0.1;
+pub const REBUILD_INTERVAL: u32 = 1;
+pub const REINSERT_THRESHOLD: f32 = 2.0;
+pub const FULL_REBUILD_RATIO: f32 = 0.5;
+
+pub const BULLET_SPAWN_ITER: i32 = 100;
+pub const BULLET_SPAWN_JITTER: i32 = 20;
+pub const BULLET_SPAWN_DELAY: f64 = 0.1;
+pub const FRAME_TIME_SMOOTHING: f32 = 0.1;
+pub const BULLET_RADIUS: f32 = 1.0;
+pub const SPAWN_MAX_RETRIES: usize = 10;
+pub const EDGE_SPAWN_OFFSET: f32 = 10.0;
+pub const DAMAGE_PER_AREA: f32 = 1.0;
+pub const BOUNCE_IMPULSE: f32 = 1.0;
+pub const BOUNCE_DECAY: f32 = 1.2;
+pub const WALL_RESTITUTION: f32 = 0.8;
+pub const ENTITY_RESTITUTION: f32 = 1.0;
+pub const CONTACT_EPSILON: f32 = 0.01;
+pub const BULLET_MAX_SPEED: f32 = 600.0;
+pub const FRAGMENT_COUNT: usize = 4;
+pub const FRAGMENT_RADIUS_SCALE: f32 = 0.5;
+pub const FRAGMENT_SPEED: f32 = 150.0;
+pub const FRAGMENT_LIFETIME: f32 = 1.0;
+pub const MAX_STUCK_BULLETS: usize = 32;
+pub const MAGNET_RADIUS: f32 = 40.0;
+pub const MAGNET_STRENGTH: f32 = 200.0;
@@ -1,405 +1,414 @@
-use macroquad::{prelude::*};
+use std::collections::HashSet;
+
+use macroquad::prelude::*;
+
+use qtdemo::config::{Config, PresentMode};
+use qtdemo::entity::{ControlMode, Movable, player_query_rect};
+use qtdemo::highscore::HighScore;
+use qtdemo::input::{Action, KeyBindings, active_actions};
+use qtdemo::quadtree::{BroadPhase, SpatialGrid, compare_broad_phases};
+use qtdemo::recording::FrameRecorder;
+use qtdemo::render::{
+    DrawShape, RenderStyle, draw_activity, draw_candidate_lines, draw_impact_predictions, draw_occupied, draw_query_coverage,
+    draw_reference_grid, draw_tree_lod, draw_velocity_lines, sort_by_z,
+};
+use qtdemo::spawner::{place_bullet, remove_nearest_bullet};
+use qtdemo::stats::candidate_ratio;
+use qtdemo::timing::FrameLimiter;
+use qtdemo::world::{HitResponse, World};
+use qtdemo::*;
 
-use std::marker::Copy;
-use std::clone::Clone;
+fn window_conf() -> Conf {
+    Conf {
+        window_title: String::from("QuadTree Demo"),
+        window_width: WINDOW_WIDTH,
+        window_height: WINDOW_HEIGHT,
+        high_dpi: false,
+        fullscreen: false,
+        sample_count: 1,
+        window_resizable: false,
+        icon: None,
+    }
+}
 
-const PLAYER_VELOCITY: f32 = 300.0;
+// Fixed timestep run for performance reports, never opens a window
+fn run_headless(config: &Config) {
+    let mut world = World::new(config);
 
-const WINDOW_WIDTH: i32 = 1000;
-const WINDOW_HEIGHT: i32 = 600;
+    for _ in 0..config.headless_frames {
+        world.step(FRAME_CAP_DT as f32);
+    }
 
-const QUADTREE_REGION_LIMIT: usize = 10;
+    println!("{}", world.stats.summary());
+    println!("{}", world.stats.latency_summary());
+}
 
-const BULLET_SPAWN_ITER: i32 = 100;
-const BULLET_SPAWN_DELAY: f64 = 0.1;
-const BULLET_RADIUS: f32 = 1.0;
+fn main() {
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
 
-trait Collidable {
-    fn bounding_box(&self) -> Circle;
-}
+    if config.headless_frames > 0 {
+        run_headless(&config);
+        return;
+    }
 
-// TODO: Query with rect area instead of a point
-struct QuadNode {
-    limit: usize,
-    region: Rect,
-    points: Vec<(u32, Vec2)>,
-    regions: Vec<Box<QuadNode>>
+    macroquad::Window::from_config(window_conf(), run(config));
 }
 
-impl QuadNode {
-    fn new(region: Rect, limit: usize) -> Self {
-        Self {
-            limit,
-            region,
-            points: Vec::new(),
-            regions: Vec::new()
-        }
-    }
+async fn run(config: Config) {
+    let mut world = World::new(&config);
+    let mut high_score = HighScore::load(HIGHSCORE_PATH);
+
+    let mut compare_broad_phase = false;
+    let mut show_impacts = false;
+    let mut show_velocities = false;
+    let mut show_candidates = false;
+    let mut show_candidate_lines = false;
+    let mut show_query_coverage = false;
+    let mut show_activity = false;
+    let mut zoom = 1.0;
+    let mut show_reference_grid = false;
+    let mut occupied_only = false;
+    let mut frame_limiter = FrameLimiter::new(FRAME_CAP_DT);
+    frame_limiter.enabled = config.present_mode == PresentMode::Uncapped;
+    let mut recorder = FrameRecorder::new(RECORDING_DIR, RECORDING_FRAMES);
+    let style = RenderStyle::default();
+    let bindings = KeyBindings::default();
+    let mut paused = false;
+    // Regions tested by the player query when explain mode was entered, `explain_step` of them are shown
+    let mut explain: Option<Vec<(Rect, bool)>> = None;
+    let mut explain_step = 0;
+
+    prevent_quit();
 
-    fn make_regions(&self) -> Vec<Box<QuadNode>> {
-        let x = self.region.x;
-        let y = self.region.y;
-        let hw = self.region.w / 2.0;
-        let hh = self.region.h / 2.0;
-
-        vec![
-            Box::new(QuadNode::new(Rect::new(x, y, hw, hh), self.limit)),
-            Box::new(QuadNode::new(Rect::new(x + hw, y, hw, hh), self.limit)),
-            Box::new(QuadNode::new(Rect::new(x, y + hh, hw, hh), self.limit)),
-            Box::new(QuadNode::new(Rect::new(x + hw, y + hh, hw, hh), self.limit)),
-        ]
-    }
+    // TODO: time interval based bullet spawning system
+    loop {
+        let start_time = get_time();
+        clear_background(BLACK);
+        let tpf = get_frame_time();
 
-    fn query(&self, query_area: &Rect) -> Vec<(u32, Vec2)> {
-        let mut ids = Vec::new();
+        if is_quit_requested() || is_key_pressed(KeyCode::Q) {
+            println!("{}", world.stats.summary());
 
-        for node in &self.regions {
-            if node.in_region(query_area) {
-                if node.regions.len() > 0 {
-                    ids.append(&mut node.query(query_area));
-                } else {
-                    ids.append(&mut node.points.clone());
+            if high_score.record(world.score, world.stats.total_frame_time) {
+                if let Err(err) = high_score.save(HIGHSCORE_PATH) {
+                    eprintln!("could not save high score: {}", err);
                 }
             }
+            break;
         }
 
-        ids
-    }
+        let mut camera = Camera2D::from_display_rect(world.tree.region);
+        camera.zoom *= zoom;
+        let cursor = camera.screen_to_world(mouse_position().into());
 
-    fn draw(&self) {
-        let r = self.region;
-        draw_rectangle_lines(r.x, r.y, r.w, r.h, 1.0, GREEN);
+        // Input related stuff
+        {
+            let (_, wheel) = mouse_wheel();
+            if wheel > 0.0 {
+                zoom = (zoom * ZOOM_STEP).min(MAX_ZOOM);
+            } else if wheel < 0.0 {
+                zoom = (zoom / ZOOM_STEP).max(MIN_ZOOM);
+            }
 
-        for region in &self.regions {
-            region.draw();
-        }
-    }
+            let keys_in = |check: fn(KeyCode) -> bool| -> HashSet<KeyCode> {
+                bindings.bindings().into_iter().map(|(_, key)| key).filter(|key| check(*key)).collect()
+            };
+            let held = active_actions(&keys_in(is_key_down), &bindings);
+            let pressed = active_actions(&keys_in(is_key_pressed), &bindings);
 
-    fn add(&mut self, id: u32, position: &Vec2) {
-        if !self.region.contains(position.clone()) {
-            return;
-        }
-
-        if self.regions.len() == 0 {
-            if self.points.len() == self.limit {
-                self.split();
-                self.add(id, position);
-            } else {
-                self.points.push((id, position.clone()));
+            if pressed.contains(&Action::Pause) {
+                paused = !paused;
             }
 
-            return;
-        }
-
-        for region in &mut self.regions {
-            region.add(id, position);
-        }
-    }
+            let player = &mut world.player;
 
-    fn split(&mut self) {
-        self.regions = self.make_regions();
+            if is_key_pressed(KeyCode::K) {
+                player.toggle_control();
+            }
 
-        for point in &self.points {
-            let (id, position) = &point;
+            let dash_requested = held.contains(&Action::Dash);
+            player.dash.update(tpf, dash_requested && player.control == ControlMode::Keyboard);
 
-            for region in &mut self.regions {
-                if self.region.contains(position.clone()) {
-                    region.add(*id, position);
+            let movable: &mut dyn Movable = &mut player.entity;
+            match player.control {
+                ControlMode::Mouse => {
+                    movable.set_position(cursor);
+                }
+                ControlMode::Keyboard => {
+                    let mut direction = Vec2::ZERO;
+                    if held.contains(&Action::MoveUp) { direction.y -= 1.0; }
+                    if held.contains(&Action::MoveDown) { direction.y += 1.0; }
+                    if held.contains(&Action::MoveLeft) { direction.x -= 1.0; }
+                    if held.contains(&Action::MoveRight) { direction.x += 1.0; }
+
+                    player.input = direction.normalize_or_zero();
                 }
             }
-        }
 
-        self.points.clear();
-    }
-
-    fn in_region(&self, query_area: &Rect) -> bool {
-        self.region.intersect(query_area.clone()).is_some()
-    }
-}
-
-#[derive(Copy, Clone)]
-struct Entity {
-    position: Vec2,
-    bouding_box: Circle,
-}
-
-impl Collidable for Entity {
-    fn bounding_box(&self) -> Circle {
-        self.bouding_box.clone()
-    }
-}
+            let radius = player.entity.bouding_box.r;
+            if is_key_pressed(KeyCode::RightBracket) {
+                player.set_radius((radius + PLAYER_RADIUS_STEP).min(PLAYER_MAX_RADIUS));
+            }
+            if is_key_pressed(KeyCode::LeftBracket) {
+                player.set_radius((radius - PLAYER_RADIUS_STEP).max(PLAYER_MIN_RADIUS));
+            }
 
-struct Player {
-    entity: Entity
-}
+            if is_key_pressed(KeyCode::E) {
+                world.spawner.toggle_mode();
+            }
 
-impl Player {
-    fn new(radius: f32, position: Vec2) -> Self {
-        Self {
-            entity: Entity {
-                position,
-                bouding_box: Circle::new(position.x, position.y, radius)
-            },
-        }
-    }
-}
+            if is_key_pressed(KeyCode::X) {
+                let enabled = world.spawner.enabled;
+                world.spawner.set_enabled(!enabled);
+            }
 
-struct Bullet {
-    entity: Entity,
-    falling_speed: f32,
-    forces: Vec<Vec2>
-}
+            if is_key_pressed(KeyCode::G) {
+                compare_broad_phase = !compare_broad_phase;
+            }
 
-impl Bullet {
-    fn new(position: Vec2, radius: f32, falling_speed: f32) -> Self {
-        Self {
-            entity: Entity {
-                position,
-                bouding_box: Circle::new(position.x, position.y, radius)
-            },
-            falling_speed,
-            forces: Vec::new()
-        }
-    }
+            if pressed.contains(&Action::ToggleGrid) {
+                show_reference_grid = !show_reference_grid;
+            }
 
-    fn fall(&mut self, tpf: f32) {
-        self.entity.position.y += tpf * self.falling_speed;
-        self.entity.bouding_box.y = self.entity.position.y;
-    }
+            if is_key_pressed(KeyCode::O) {
+                occupied_only = !occupied_only;
+            }
 
-    // With each update, applied forces should get smaller and smaller till they get deleted from `forces`
-    fn update(&mut self, tpf: f32) {
-        self.fall(tpf);
-        self.apply_forces(tpf);
-    }
+            if is_key_pressed(KeyCode::B) {
+                show_query_coverage = !show_query_coverage;
+            }
 
-    fn register_force(&mut self, force: Vec2) {
-        if force.length() > 0.01 {
-            self.forces.push(force);
-        }
-    }
+            if is_key_pressed(KeyCode::U) {
+                show_activity = !show_activity;
+            }
 
-    fn apply_forces(&mut self, tpf: f32) {
-        let mut n_low_forces = 0;
+            if is_key_pressed(KeyCode::T) {
+                show_impacts = !show_impacts;
+            }
 
-        for force in &mut self.forces {
-            self.entity.move_by(force.clone(), tpf);
-            force.x /= 1.2;
-            force.y /= 1.2;
+            if is_key_pressed(KeyCode::I) {
+                show_velocities = !show_velocities;
+            }
 
-            if force.length() <= 0.01 {
-                n_low_forces += 1;
+            if is_key_pressed(KeyCode::Key1) {
+                show_candidates = !show_candidates;
             }
-        }
 
-        if n_low_forces > 0 {
-            self.forces = self.forces.iter()
-                .filter(|f| f.length() > 0.01)
-                .copied()
-                .collect();
-        }
-    }
-}
+            if is_key_pressed(KeyCode::Key2) {
+                show_candidate_lines = !show_candidate_lines;
+            }
 
-trait DrawShape {
-    fn draw(self: &Self) {}
-}
+            if is_key_pressed(KeyCode::Key3) {
+                world.pin_player = !world.pin_player;
+            }
 
-impl DrawShape for Player {
-    fn draw(&self) {
-        draw_circle(self.entity.position.x, self.entity.position.y, self.entity.bouding_box.r, RED);
-    }
-}
+            if is_key_pressed(KeyCode::F) {
+                frame_limiter.toggle();
+            }
 
-impl DrawShape for Bullet {
-    fn draw(&self) {
-        draw_circle(self.entity.position.x, self.entity.position.y, self.entity.bouding_box.r, WHITE);
-    }
-}
+            if is_key_pressed(KeyCode::C) {
+                world.toggle_broad_phase();
+            }
 
-trait Movable {
-    fn move_by(&mut self, offset: Vec2, tpf: f32);
-    fn set_position(&mut self, position: Vec2);
-}
+            if is_key_pressed(KeyCode::H) {
+                world.toggle_hit_response();
+            }
 
-impl Movable for Entity {
-    fn move_by(&mut self, offset: Vec2, tpf: f32) {
-        self.position.x += offset.x * tpf * PLAYER_VELOCITY;
-        self.position.y += offset.y * tpf * PLAYER_VELOCITY;
+            if is_key_pressed(KeyCode::M) {
+                world.magnetism = !world.magnetism;
+            }
 
-        self.bouding_box.x = self.position.x;
-        self.bouding_box.y = self.position.y;
-    }
+            let limit = world.tree.limit();
+            if is_key_pressed(KeyCode::Equal) || is_key_pressed(KeyCode::KpAdd) {
+                world.set_region_limit(limit + 1);
+            }
+            if is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::KpSubtract) {
+                world.set_region_limit(limit.saturating_sub(1));
+            }
 
-    fn set_position(&mut self, position: Vec2) {
-        self.position = position;
-        self.bouding_box.x = position.x;
-        self.bouding_box.y = position.y;
-    }
-}
+            if is_key_pressed(KeyCode::V) && !recorder.active {
+                if let Err(err) = recorder.start() {
+                    eprintln!("could not start recording: {}", err);
+                }
+            }
 
-fn try_hit(player: &Player, bullets: &Vec<Bullet>, possible_ids: Vec<u32>) -> Option<Vec<usize>> {
-    let mut ids = Vec::new();
+            if is_key_pressed(KeyCode::L) {
+                world.wrap = !world.wrap;
+            }
 
-    for i in possible_ids {
-        if bullets[i as usize].entity.bouding_box.overlaps(&player.entity.bouding_box) {
-            ids.push(i as usize);
-        }
-    }
+            if is_key_pressed(KeyCode::Z) {
+                world.walls = !world.walls;
+            }
 
-    if ids.len() > 0 {
-        return Some(ids);
-    }
+            if is_key_pressed(KeyCode::Y) {
+                world.verify_hits = !world.verify_hits;
+            }
 
-    None
-}
+            if is_key_pressed(KeyCode::J) {
+                explain = match explain {
+                    Some(_) => None,
+                    None => {
+                        let area = player_query_rect(&world.player);
+                        Some(world.tree.visits(&area).map(|(node, overlaps)| (node.region, overlaps)).collect())
+                    }
+                };
+                explain_step = 0;
+            }
 
-struct BulletSpawner {
-    is_active: bool
-}
+            if let Some(visits) = &explain {
+                if is_key_pressed(KeyCode::N) {
+                    explain_step = (explain_step + 1).min(visits.len());
+                }
+            }
 
-impl BulletSpawner {
-    fn new() -> Self {
-        Self { is_active: true }
-    }
+            if is_key_pressed(KeyCode::P) {
+                world.manual_placement = !world.manual_placement;
+            }
 
-    fn spawn(&mut self, no_bullets: i32, radius: f32) -> Option<Vec<Bullet>> {
-        if !self.is_active {
-            return None
+            if world.manual_placement {
+                if is_mouse_button_pressed(MouseButton::Left) && world.spawn_room(1) > 0 {
+                    place_bullet(&mut world.bullets, &mut world.tree, cursor);
+                    world.stats.record_spawn(1);
+                }
+                if is_mouse_button_pressed(MouseButton::Right) {
+                    remove_nearest_bullet(&mut world.bullets, &mut world.tree, cursor);
+                }
+            }
         }
 
-        let bullets = (0..no_bullets).into_iter()
-            .map(|_| Bullet::new(
-                Vec2::new(
-                    rand::gen_range(0.0, WINDOW_WIDTH as f32), 
-                    //rand::gen_range(0.0, WINDOW_HEIGHT as f32)),
-                    0.0),
-                radius,
-                rand::gen_range(100.0, 300.0))
-            )
-            .collect();
-
-        self.is_active = false;
+        if !paused {
+            world.step(tpf);
+        }
 
-        Some(bullets)
-    }
+        // Drawing 
+        {
+            set_camera(&camera);
 
-    fn reset(&mut self) {
-        self.is_active = true;
-    }
-}
+            if show_reference_grid {
+                draw_reference_grid(REFERENCE_GRID_SPACING, world.tree.region);
+            }
 
-fn window_conf() -> Conf {
-    Conf {
-        window_title: String::from("QuadTree Demo"),
-        window_width: WINDOW_WIDTH,
-        window_height: WINDOW_HEIGHT,
-        high_dpi: false,
-        fullscreen: false,
-        sample_count: 1,
-        window_resizable: false,
-        icon: None,
-    }
-}
+            if show_activity {
+                draw_activity(&world.tree);
+            }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let screen_middle = Vec2::new(WINDOW_WIDTH as f32 / 2.0, WINDOW_HEIGHT as f32 / 2.0);
-    let mut bullets_in_scene = Vec::new();
-    let mut bullet_spawner = BulletSpawner::new();
-    let mut player = Player::new(100.0, screen_middle);
+            let mut layers: Vec<(i32, &dyn DrawShape)> = vec![(style.player_z, &world.player)];
+            for bullet in world.bullets.iter().chain(world.stuck.iter().map(|stuck| &stuck.bullet)) {
+                layers.push((style.bullet_z, bullet));
+            }
 
-    let mut bullet_spawner_trigger_time = 0.0;
+            sort_by_z(&mut layers);
+            for (_, drawable) in layers {
+                drawable.draw(&style);
+            }
 
-    let qregion = Rect::new(0.0, 0.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32);
-    let mut qtree = QuadNode::new(
-        qregion.clone(),
-        QUADTREE_REGION_LIMIT
-    );
+            if occupied_only {
+                draw_occupied(&world.tree, &style);
+            } else {
+                draw_tree_lod(&world.tree, zoom, LOD_THRESHOLD_PX, &style);
+            }
 
-    qtree.regions = qtree.make_regions();
+            for &missed in &world.missed_hits {
+                let circle = world.bullets[missed].entity.bouding_box;
+                draw_circle_lines(circle.x, circle.y, circle.r + 2.0, 1.0, MAGENTA);
+            }
 
-    // TODO: time interval based bullet spawning system
-    loop {
-        let start_time = get_time();
-        clear_background(BLACK);
-        let tpf = get_frame_time();
+            if let Some(visits) = &explain {
+                for (i, (r, overlaps)) in visits.iter().take(explain_step).enumerate() {
+                    let color = match (i + 1 == explain_step, overlaps) {
+                        (true, true) => YELLOW,
+                        (true, false) => RED,
+                        (false, _) => DARKGRAY,
+                    };
+                    draw_rectangle_lines(r.x, r.y, r.w, r.h, 2.0, color);
+                }
+            }
 
-        if let Some(mut bullets) = bullet_spawner.spawn(BULLET_SPAWN_ITER, BULLET_RADIUS) {
-            bullets_in_scene.append(&mut bullets);
-        }
+            if show_candidate_lines {
+                draw_candidate_lines(world.player.entity.position, &world.frame_candidates);
+            }
 
-        for (i, bullet) in bullets_in_scene.iter().enumerate() {
-            qtree.add(i as u32, &bullet.entity.position);
-        }
+            if show_query_coverage {
+                draw_query_coverage(&world.player, &world.tree);
+            }
 
-        // Drawing 
-        {
-            let drawable: &dyn DrawShape = &player;
-            drawable.draw();
+            if show_impacts {
+                draw_impact_predictions(&world.player, &world.bullets, IMPACT_PREDICTION_COUNT);
+            }
 
-            for bullet in &mut bullets_in_scene {
-                let drawable: &dyn DrawShape = bullet;
-                drawable.draw();
+            if show_velocities {
+                draw_velocity_lines(&world.bullets, VELOCITY_LINE_SCALE);
             }
 
-            qtree.draw();
-        }
+            if compare_broad_phase {
+                let mut grid = SpatialGrid::new(world.tree.region, GRID_CELL_SIZE);
+                for (i, bullet) in world.bullets.iter().enumerate() {
+                    grid.insert(i as u32, &bullet.entity.position);
+                }
 
-        // Input related stuff
-        {
-            let movable: &mut dyn Movable = &mut player.entity;
-            let (mouse_x, mouse_y) = mouse_position();
-            movable.set_position(Vec2::new(mouse_x, mouse_y));
-        }
+                let player_rect = player_query_rect(&world.player);
 
+                for cell in grid.candidate_regions(&player_rect) {
+                    draw_rectangle_lines(cell.x, cell.y, cell.w, cell.h, 2.0, BLUE);
+                }
 
-        // Handle collisition player-bullets, if a bullet gets hit bounce it back
-        {
-            let player_rect = Rect::new(
-                player.entity.position.x - player.entity.bouding_box.r,
-                player.entity.position.y - player.entity.bouding_box.r,
-                player.entity.bouding_box.r * 2.0,
-                player.entity.bouding_box.r * 2.0,
-            );
-            let ids = qtree.query(&player_rect).iter().map(|p| p.0).collect();
-            let player_has_hit = try_hit(&player, &bullets_in_scene, ids);
-
-            if let Some(hit_ids) = player_has_hit {
-                for hit_id in hit_ids {
-                    let hit_bullet = &mut bullets_in_scene[hit_id];
-                    let bullet_pos: Vec2 = hit_bullet.entity.bouding_box.point();
-                    let player_pos: Vec2 = player.entity.bouding_box.point();
-
-                    let mut direction = bullet_pos - player_pos;
-                    direction = direction.normalize() * 1 as f32;
-
-                    hit_bullet.register_force(direction);
+                for leaf in world.tree.candidate_regions(&player_rect) {
+                    draw_rectangle_lines(leaf.x, leaf.y, leaf.w, leaf.h, 2.0, YELLOW);
                 }
+
+                let comparison = compare_broad_phases(&world.tree, &grid, &player_rect);
+                set_default_camera();
+                draw_text(&format!("quadtree candidates: {}", comparison.quadtree_candidates), 10.0, 20.0, 20.0, YELLOW);
+                draw_text(&format!("grid candidates: {}", comparison.grid_candidates), 10.0, 40.0, 20.0, BLUE);
+
+                let (_, query_stats) = world.tree.query_with_stats(&player_rect);
+                draw_text(
+                    &format!(
+                        "nodes visited: {}, leaves visited: {}, points examined: {}",
+                        query_stats.nodes_visited, query_stats.leaves_visited, query_stats.points_examined,
+                    ),
+                    10.0, 60.0, 20.0, WHITE,
+                );
             }
 
-            for bullet in &mut bullets_in_scene {
-                bullet.update(tpf);
+            set_default_camera();
+
+            if show_candidates {
+                draw_text(
+                    &format!(
+                        "candidates: {} / {} ({:.1}%), hits: {}",
+                        world.frame_candidates.len(),
+                        world.bullets.len(),
+                        candidate_ratio(world.frame_candidates.len(), world.bullets.len()) * 100.0,
+                        world.frame_hits,
+                    ),
+                    10.0, 80.0, 20.0, WHITE,
+                );
+                draw_text(&format!("leaf migrations: {}", world.leaf_migrations), 10.0, 100.0, 20.0, WHITE);
             }
-        }
 
-        next_frame().await;
+            if world.hit_response != HitResponse::Bounce {
+                draw_text(
+                    &format!("score: {} (best {})", world.score, high_score.score.max(world.score)),
+                    10.0, screen_height() - 10.0, 20.0, WHITE,
+                );
+            }
+        }
 
-        bullet_spawner_trigger_time += get_time() - start_time;
-        if bullet_spawner_trigger_time > BULLET_SPAWN_DELAY {
-            bullet_spawner_trigger_time = 0.0;
-            bullet_spawner.reset();
+        if let Some(path) = recorder.next_path() {
+            get_screen_data().export_png(&path);
+        }
 
-            bullets_in_scene = bullets_in_scene
-                .into_iter()
-                .filter(|b| b.entity.position.y < WINDOW_HEIGHT as f32)
-                .collect();
+        if frame_limiter.enabled {
+            std::thread::sleep(frame_limiter.wait(get_time() - start_time));
         }
-        qtree = QuadNode::new(
-            qregion.clone(),
-            QUADTREE_REGION_LIMIT
-        );
 
-        qtree.regions = qtree.make_regions();
+        next_frame().await;
     }
 }
@@ -0,0 +1,2358 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use macroquad::math::{IVec2, Rect, Vec2};
+use rand::Rng;
+
+use crate::{ACTIVITY_DECAY_RATE, ACTIVITY_PER_INSERT, DEGENERATE_DEPTH_RATIO, FIT_PADDING, QUADTREE_MAX_DEPTH};
+
+// Leaf capacity as a function of node depth, the root being depth 0
+pub type LimitPolicy = Arc<dyn Fn(usize) -> usize + Send + Sync>;
+
+// Queries only borrow the tree, so once built on the main thread it can be handed to workers
+// as an `Arc<QuadNode>`, this fails to compile if a field ever breaks that
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<QuadNode>();
+};
+
+// TODO: Query with rect area instead of a point
+// `stamps` holds the generation each entry of `points` was inserted in, `generation` is bumped by `clear`
+// `radii` is zero for plain points, circles get stored in every leaf their bounds overlap
+// `weights` is what each entry counts for in `weighted_centroid`, 1 unless added with `add_weighted`
+#[derive(Clone)]
+pub struct QuadNode {
+    limit: usize,
+    limit_policy: Option<LimitPolicy>,
+    depth: usize,
+    max_depth: usize,
+    // Splits never make children narrower or shorter than this, 0 only stops at `max_depth`
+    min_node_size: f32,
+    // Children per side, every split makes `branch * branch` of them
+    branch: usize,
+    pub region: Rect,
+    generation: u64,
+    points: Vec<(u32, Vec2)>,
+    stamps: Vec<u64>,
+    radii: Vec<f32>,
+    weights: Vec<f32>,
+    // Entries stored in this node's leaves, kept up to date by every insert and removal
+    subtree_count: usize,
+    // Last position of every id inserted through this node's public methods, empty on children
+    ids: HashMap<u32, Vec2>,
+    // Bumped by inserts and splits, fades with `decay_activity`, starts over when rebuilt
+    activity: f32,
+    // Set on leaves that can't split any further and went past their capacity, their entries are then kept
+    // sorted by id so lookups stay logarithmic however many coincident points pile up
+    overflow: bool,
+    regions: Vec<QuadNode>
+}
+
+impl QuadNode {
+    // Root covering `region`, queries work on it right away whether it has split yet or not
+    pub fn with_root(region: Rect, limit: usize) -> Self {
+        Self {
+            limit,
+            limit_policy: None,
+            depth: 0,
+            max_depth: QUADTREE_MAX_DEPTH,
+            min_node_size: 0.0,
+            branch: 2,
+            region,
+            generation: 0,
+            points: Vec::new(),
+            stamps: Vec::new(),
+            radii: Vec::new(),
+            weights: Vec::new(),
+            subtree_count: 0,
+            ids: HashMap::new(),
+            activity: 0.0,
+            overflow: false,
+            regions: Vec::new()
+        }
+    }
+
+    // Same as `with_root` with room for `expected_points` entries before the first reallocation
+    pub fn with_capacity(region: Rect, limit: usize, expected_points: usize) -> Self {
+        let mut node = Self::with_root(region, limit);
+        node.points.reserve(expected_points);
+        node.stamps.reserve(expected_points);
+        node.radii.reserve(expected_points);
+        node.weights.reserve(expected_points);
+        node.regions.reserve(node.branch * node.branch);
+        node
+    }
+
+    // Root sized to the tight bounds of `points` grown by `FIT_PADDING` on every side, holding
+    // every point
+    pub fn from_fitted(points: &[(u32, Vec2)], limit: usize) -> Self {
+        let (min, max) = points.iter()
+            .map(|(_, position)| *position)
+            .fold(None, |bounds: Option<(Vec2, Vec2)>, position| match bounds {
+                Some((min, max)) => Some((min.min(position), max.max(position))),
+                None => Some((position, position)),
+            })
+            .unwrap_or((Vec2::ZERO, Vec2::ZERO));
+
+        let region = Rect::new(
+            min.x - FIT_PADDING,
+            min.y - FIT_PADDING,
+            max.x - min.x + FIT_PADDING * 2.0,
+            max.y - min.y + FIT_PADDING * 2.0,
+        );
+
+        let mut tree = Self::with_root(region, limit);
+
+        for (id, position) in points {
+            tree.add(*id, position);
+        }
+
+        tree
+    }
+
+    // Leaves at `max_depth` stop splitting and grow past their limit instead
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Leaves whose children would be smaller than `min_node_size` on either side stop splitting
+    // and grow past their limit instead, keeps clustered points from carving sub-pixel regions
+    pub fn with_min_node_size(mut self, min_node_size: f32) -> Self {
+        self.min_node_size = min_node_size;
+        self
+    }
+
+    // Splits into a `branch` x `branch` grid instead of quadrants, e.g. 4 for a shallower tree
+    // with 16 children per node
+    pub fn with_branch(mut self, branch: usize) -> Self {
+        self.branch = branch.max(2);
+        self
+    }
+
+    // Leaf capacity follows `policy` instead of a fixed limit, e.g. `|depth| limit * (depth + 1)`
+    pub fn with_limit_policy(region: Rect, policy: LimitPolicy) -> Self {
+        let mut node = Self::with_root(region, policy(0));
+        node.limit_policy = Some(policy);
+        node
+    }
+
+    fn child(&self, region: Rect) -> QuadNode {
+        QuadNode {
+            limit: self.limit,
+            limit_policy: self.limit_policy.clone(),
+            depth: self.depth + 1,
+            max_depth: self.max_depth,
+            min_node_size: self.min_node_size,
+            branch: self.branch,
+            region,
+            generation: self.generation,
+            points: Vec::new(),
+            stamps: Vec::new(),
+            radii: Vec::new(),
+            weights: Vec::new(),
+            subtree_count: 0,
+            ids: HashMap::new(),
+            activity: 0.0,
+            overflow: false,
+            regions: Vec::new()
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    // Only affects regions created afterwards, callers rebuild the tree to apply it everywhere
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    fn capacity(&self) -> usize {
+        match &self.limit_policy {
+            Some(policy) => policy(self.depth),
+            None => self.limit,
+        }
+    }
+
+    // Row major, so with the default branch of 2 the order is top left, top right, bottom left,
+    // bottom right
+    fn child_regions(&self) -> Vec<Rect> {
+        let w = self.region.w / self.branch as f32;
+        let h = self.region.h / self.branch as f32;
+
+        (0..self.branch * self.branch)
+            .map(|i| {
+                let (col, row) = ((i % self.branch) as f32, (i / self.branch) as f32);
+                Rect::new(self.region.x + w * col, self.region.y + h * row, w, h)
+            })
+            .collect()
+    }
+
+    fn can_split(&self) -> bool {
+        let branch = self.branch as f32;
+
+        self.depth < self.max_depth
+            && self.region.w / branch >= self.min_node_size
+            && self.region.h / branch >= self.min_node_size
+    }
+
+    fn make_regions(&self) -> Vec<QuadNode> {
+        self.child_regions()
+            .into_iter()
+            .map(|region| self.child(region))
+            .collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.subtree_count
+    }
+
+    // Entries stored directly in this node, only leaves hold any
+    pub fn points(&self) -> &[(u32, Vec2)] {
+        &self.points
+    }
+
+    // Child nodes, empty for a leaf
+    pub fn children(&self) -> impl Iterator<Item = &QuadNode> {
+        self.regions.iter()
+    }
+
+    pub fn node_count(&self) -> usize {
+        1 + self.regions.iter().map(|region| region.node_count()).sum::<usize>()
+    }
+
+    // Drops every stored point and child region, stamping later inserts with a new generation
+    pub fn clear(&mut self) {
+        self.generation += 1;
+        self.points.clear();
+        self.stamps.clear();
+        self.radii.clear();
+        self.weights.clear();
+        self.subtree_count = 0;
+        self.ids.clear();
+        self.overflow = false;
+        self.regions.clear();
+    }
+
+    // Every entry stored in a leaf overlapping `query_area`, a root that never split is a leaf too
+    pub fn query(&self, query_area: &Rect) -> Vec<(u32, Vec2)> {
+        if !self.in_region(query_area) {
+            return Vec::new();
+        }
+
+        if self.regions.is_empty() {
+            return self.points.clone();
+        }
+
+        self.regions.iter().flat_map(|node| node.query(query_area)).collect()
+    }
+
+    // `query(query_area).len()` without collecting anything, subtrees the area fully covers are
+    // counted straight from their cached `subtree_count`
+    pub fn count_in(&self, query_area: &Rect) -> usize {
+        if self.subtree_count == 0 || !self.in_region(query_area) {
+            return 0;
+        }
+
+        if self.regions.is_empty() || rect_covers(query_area, &self.region) {
+            return self.subtree_count;
+        }
+
+        self.regions.iter().map(|node| node.count_in(query_area)).sum()
+    }
+
+    // Weighted average position of the points inside `query_area`, `None` when there are none
+    // or their weights sum to zero
+    pub fn weighted_centroid(&self, query_area: &Rect) -> Option<Vec2> {
+        let mut entries = Vec::new();
+        self.collect_weighted(query_area, &mut entries);
+
+        // Circles show up once per leaf they overlap but count only once
+        entries.sort_by_key(|(id, _, _)| *id);
+        entries.dedup_by_key(|(id, _, _)| *id);
+
+        let (sum, total) = entries.iter()
+            .fold((Vec2::ZERO, 0.0), |(sum, total), (_, position, weight)| (sum + *position * *weight, total + weight));
+
+        (total != 0.0).then(|| sum / total)
+    }
+
+    fn collect_weighted(&self, query_area: &Rect, entries: &mut Vec<(u32, Vec2, f32)>) {
+        if !self.in_region(query_area) {
+            return;
+        }
+
+        for ((id, position), weight) in self.points.iter().zip(&self.weights) {
+            if query_area.contains(*position) {
+                entries.push((*id, *position, *weight));
+            }
+        }
+
+        for node in &self.regions {
+            node.collect_weighted(query_area, entries);
+        }
+    }
+
+    // Same matches as `query`, borrowed from the tree instead of copied
+    pub fn query_refs(&self, query_area: &Rect) -> Vec<&(u32, Vec2)> {
+        if !self.in_region(query_area) {
+            return Vec::new();
+        }
+
+        if self.regions.is_empty() {
+            return self.points.iter().collect();
+        }
+
+        self.regions.iter().flat_map(|node| node.query_refs(query_area)).collect()
+    }
+
+    // Same matches as `query` grouped by the leaf holding them, empty leaves are left out
+    pub fn query_by_leaf(&self, query_area: &Rect) -> Vec<(Rect, Vec<(u32, Vec2)>)> {
+        if !self.in_region(query_area) {
+            return Vec::new();
+        }
+
+        if self.regions.is_empty() {
+            return if self.points.is_empty() { Vec::new() } else { vec![(self.region, self.points.clone())] };
+        }
+
+        self.regions.iter().flat_map(|node| node.query_by_leaf(query_area)).collect()
+    }
+
+    // `query` keeping only the points accepted by `pred`, filtered while visiting each leaf
+    pub fn query_where(&self, query_area: &Rect, pred: impl Fn(u32, Vec2) -> bool) -> Vec<(u32, Vec2)> {
+        let mut ids = Vec::new();
+        self.collect_where(query_area, &pred, &mut ids);
+        ids
+    }
+
+    fn collect_where(&self, query_area: &Rect, pred: &impl Fn(u32, Vec2) -> bool, ids: &mut Vec<(u32, Vec2)>) {
+        if !self.in_region(query_area) {
+            return;
+        }
+
+        if self.regions.is_empty() {
+            ids.extend(self.points.iter().filter(|(id, position)| pred(*id, *position)));
+            return;
+        }
+
+        for node in &self.regions {
+            node.collect_where(query_area, pred, ids);
+        }
+    }
+
+    // Same results as `query` plus how much of the tree it had to touch to find them
+    pub fn query_with_stats(&self, query_area: &Rect) -> (Vec<(u32, Vec2)>, QueryStats) {
+        let mut ids = Vec::new();
+        let mut stats = QueryStats::default();
+        self.collect_with_stats(query_area, &mut ids, &mut stats);
+        (ids, stats)
+    }
+
+    fn collect_with_stats(&self, query_area: &Rect, ids: &mut Vec<(u32, Vec2)>, stats: &mut QueryStats) {
+        if !self.in_region(query_area) {
+            return;
+        }
+
+        stats.nodes_visited += 1;
+
+        if self.regions.is_empty() {
+            stats.leaves_visited += 1;
+            stats.points_examined += self.points.len();
+            ids.extend_from_slice(&self.points);
+            return;
+        }
+
+        for node in &self.regions {
+            node.collect_with_stats(query_area, ids, stats);
+        }
+    }
+
+    // Same results and order as `query` without collecting them first
+    pub fn query_iter(&self, query_area: &Rect) -> QueryIter<'_> {
+        QueryIter {
+            area: *query_area,
+            stack: vec![self],
+            points: [].iter(),
+        }
+    }
+
+    // Every node `query` tests against `area`, in the order it tests them, paired with whether
+    // it overlapped. The root comes first and rejected nodes are never descended into
+    pub fn visits(&self, query_area: &Rect) -> VisitIter<'_> {
+        VisitIter {
+            area: *query_area,
+            stack: vec![self],
+        }
+    }
+
+    // Query results ordered by distance to `from`, ties broken by id
+    pub fn query_sorted(&self, query_area: &Rect, from: Vec2) -> Vec<(u32, Vec2)> {
+        let mut points = self.query(query_area);
+
+        points.sort_by(|(a_id, a), (b_id, b)| {
+            a.distance_squared(from)
+                .total_cmp(&b.distance_squared(from))
+                .then(a_id.cmp(b_id))
+        });
+
+        points
+    }
+
+    pub fn query_circle(&self, center: Vec2, radius: f32) -> Vec<(u32, Vec2)> {
+        let area = Rect::new(center.x - radius, center.y - radius, radius * 2.0, radius * 2.0);
+
+        self.query(&area)
+            .into_iter()
+            .filter(|(_, position)| position.distance_squared(center) <= radius * radius)
+            .collect()
+    }
+
+    // First point within the circle accepted by `filter`, stops traversing as soon as one is found
+    pub fn any_in_circle(&self, center: Vec2, radius: f32, filter: impl Fn(u32, Vec2) -> bool) -> Option<(u32, Vec2)> {
+        self.find_in_circle(center, radius, &filter)
+    }
+
+    fn find_in_circle(&self, center: Vec2, radius: f32, filter: &impl Fn(u32, Vec2) -> bool) -> Option<(u32, Vec2)> {
+        if distance_squared_to_rect(&self.region, center) > radius * radius {
+            return None;
+        }
+
+        let found = self.points.iter()
+            .find(|(id, position)| position.distance_squared(center) <= radius * radius && filter(*id, *position));
+        if let Some(point) = found {
+            return Some(*point);
+        }
+
+        self.regions.iter().find_map(|region| region.find_in_circle(center, radius, filter))
+    }
+
+    // Prunes with the axis aligned bounds of the rotated rect, then keeps points inside it
+    // by projecting them onto the rect's own axes
+    pub fn query_obb(&self, center: Vec2, half_extents: Vec2, angle: f32) -> Vec<(u32, Vec2)> {
+        let (sin, cos) = angle.sin_cos();
+        let aabb_half = Vec2::new(
+            cos.abs() * half_extents.x + sin.abs() * half_extents.y,
+            sin.abs() * half_extents.x + cos.abs() * half_extents.y,
+        );
+        let aabb = Rect::new(
+            center.x - aabb_half.x,
+            center.y - aabb_half.y,
+            aabb_half.x * 2.0,
+            aabb_half.y * 2.0,
+        );
+
+        self.query(&aabb)
+            .into_iter()
+            .filter(|(_, position)| {
+                let d = *position - center;
+                let local_x = d.x * cos + d.y * sin;
+                let local_y = -d.x * sin + d.y * cos;
+
+                local_x.abs() <= half_extents.x && local_y.abs() <= half_extents.y
+            })
+            .collect()
+    }
+
+    // Axis aligned ellipse, pruned by its bounding rect and filtered with the normalized equation
+    pub fn query_ellipse(&self, center: Vec2, rx: f32, ry: f32) -> Vec<(u32, Vec2)> {
+        let area = Rect::new(center.x - rx, center.y - ry, rx * 2.0, ry * 2.0);
+
+        self.query(&area)
+            .into_iter()
+            .filter(|(_, position)| {
+                let nx = (position.x - center.x) / rx;
+                let ny = (position.y - center.y) / ry;
+
+                nx * nx + ny * ny <= 1.0
+            })
+            .collect()
+    }
+
+    // Like `query`, but skips entries that were inserted before the last `clear`
+    pub fn query_current(&self, query_area: &Rect) -> Vec<(u32, Vec2)> {
+        let mut ids = Vec::new();
+        self.collect_generation(query_area, self.generation, &mut ids);
+        ids
+    }
+
+    fn collect_generation(&self, query_area: &Rect, generation: u64, ids: &mut Vec<(u32, Vec2)>) {
+        if !self.in_region(query_area) {
+            return;
+        }
+
+        if self.regions.is_empty() {
+            ids.extend(self.points.iter()
+                .zip(&self.stamps)
+                .filter(|(_, stamp)| **stamp == generation)
+                .map(|(point, _)| *point));
+            return;
+        }
+
+        for node in &self.regions {
+            node.collect_generation(query_area, generation, ids);
+        }
+    }
+
+    pub fn add(&mut self, id: u32, position: &Vec2) {
+        self.add_weighted(id, position, 1.0);
+    }
+
+    // Plain point insert counting `weight` times toward `weighted_centroid`
+    pub fn add_weighted(&mut self, id: u32, position: &Vec2, weight: f32) {
+        if self.add_entry(id, position, 0.0, weight, self.generation) > 0 {
+            self.ids.insert(id, *position);
+        }
+    }
+
+    // Radius aware insert, `id` lands in every leaf overlapped by the circle's bounds
+    pub fn add_circle(&mut self, id: u32, center: &Vec2, radius: f32) {
+        if self.add_entry(id, center, radius, 1.0, self.generation) > 0 {
+            self.ids.insert(id, *center);
+        }
+    }
+
+    // Where `id` was last inserted, `None` if it isn't stored
+    pub fn position_of(&self, id: u32) -> Option<Vec2> {
+        self.ids.get(&id).copied()
+    }
+
+    // Relocates `id` if it is already stored instead of adding a second entry, returns
+    // whether it was already there
+    pub fn add_or_update(&mut self, id: u32, position: &Vec2) -> bool {
+        match self.ids.get(&id).copied() {
+            Some(old) => self.update_position(id, &old, position),
+            None => {
+                self.add(id, position);
+                false
+            }
+        }
+    }
+
+    fn enter_overflow(&mut self) {
+        if self.overflow {
+            return;
+        }
+
+        let mut order: Vec<usize> = (0..self.points.len()).collect();
+        order.sort_by_key(|&i| self.points[i].0);
+
+        self.points = order.iter().map(|&i| self.points[i]).collect();
+        self.stamps = order.iter().map(|&i| self.stamps[i]).collect();
+        self.radii = order.iter().map(|&i| self.radii[i]).collect();
+        self.weights = order.iter().map(|&i| self.weights[i]).collect();
+        self.overflow = true;
+    }
+
+    pub fn is_overflowing(&self) -> bool {
+        self.overflow
+    }
+
+    // Regions of every leaf currently in overflow mode
+    pub fn overflowing_leaves(&self) -> Vec<Rect> {
+        if self.regions.is_empty() {
+            return if self.overflow { vec![self.region] } else { Vec::new() };
+        }
+
+        self.regions.iter().flat_map(|region| region.overflowing_leaves()).collect()
+    }
+
+    fn covers(&self, position: &Vec2, radius: f32) -> bool {
+        if radius == 0.0 {
+            return self.region.contains(*position);
+        }
+
+        self.region.overlaps(&Rect::new(position.x - radius, position.y - radius, radius * 2.0, radius * 2.0))
+    }
+
+    // Returns how much `subtree_count` grew, zero when the entry wasn't stored. A split copies
+    // circles into every child they overlap, so that can be more than the leaves it landed in
+    fn add_entry(&mut self, id: u32, position: &Vec2, radius: f32, weight: f32, stamp: u64) -> usize {
+        if !self.covers(position, radius) {
+            return 0;
+        }
+
+        if self.regions.is_empty() {
+            if self.points.len() >= self.capacity() && self.can_split() {
+                let before = self.subtree_count;
+                self.split();
+                self.add_entry(id, position, radius, weight, stamp);
+                return self.subtree_count - before;
+            }
+
+            if self.points.len() >= self.capacity() {
+                self.enter_overflow();
+            }
+
+            let index = if self.overflow {
+                self.points.partition_point(|(point_id, _)| *point_id <= id)
+            } else {
+                self.points.len()
+            };
+
+            self.points.insert(index, (id, *position));
+            self.stamps.insert(index, stamp);
+            self.radii.insert(index, radius);
+            self.weights.insert(index, weight);
+            self.subtree_count += 1;
+            self.activity = (self.activity + ACTIVITY_PER_INSERT).min(1.0);
+
+            return 1;
+        }
+
+        let added = self.regions.iter_mut()
+            .map(|region| region.add_entry(id, position, radius, weight, stamp))
+            .sum();
+        self.subtree_count += added;
+
+        added
+    }
+
+    // Removes the entry with `id`, only descending into the regions that can hold `position`
+    pub fn remove(&mut self, id: u32, position: &Vec2) -> bool {
+        self.remove_entry(id, position).is_some()
+    }
+
+    // Same as `remove`, handing back the weight the entry was stored with
+    fn remove_entry(&mut self, id: u32, position: &Vec2) -> Option<f32> {
+        if !self.region.contains(*position) {
+            return None;
+        }
+
+        let index = if self.overflow {
+            self.points.binary_search_by_key(&id, |(point_id, _)| *point_id).ok()
+        } else {
+            self.points.iter().position(|(point_id, _)| *point_id == id)
+        };
+
+        if let Some(index) = index {
+            self.points.remove(index);
+            self.stamps.remove(index);
+            self.radii.remove(index);
+            let weight = self.weights.remove(index);
+            self.subtree_count -= 1;
+            self.overflow &= self.points.len() > self.capacity();
+            self.ids.remove(&id);
+            return Some(weight);
+        }
+
+        let removed = self.regions.iter_mut().find_map(|region| region.remove_entry(id, position));
+        if removed.is_some() {
+            self.subtree_count -= 1;
+            self.ids.remove(&id);
+        }
+
+        removed
+    }
+
+    // Removes every entry within `radius` of `center` and returns their sorted ids, children
+    // left with no more entries than fit in a single leaf are merged back into one, the root included
+    pub fn remove_in_circle(&mut self, center: Vec2, radius: f32) -> Vec<u32> {
+        let mut removed = Vec::new();
+        self.remove_in_circle_inner(center, radius, &mut removed);
+
+        // Circles are stored once per leaf they overlap but removed only once
+        removed.sort_unstable();
+        removed.dedup();
+
+        if !self.regions.is_empty() && self.count() <= self.capacity() {
+            self.merge_children();
+        }
+
+        for id in &removed {
+            self.ids.remove(id);
+        }
+
+        removed
+    }
+
+    // Returns how much `subtree_count` shrank
+    fn remove_in_circle_inner(&mut self, center: Vec2, radius: f32, removed: &mut Vec<u32>) -> usize {
+        if distance_squared_to_rect(&self.region, center) > radius * radius {
+            return 0;
+        }
+
+        let before = self.points.len();
+        let mut index = 0;
+        while index < self.points.len() {
+            let (id, position) = self.points[index];
+
+            if position.distance_squared(center) <= radius * radius {
+                self.points.remove(index);
+                self.stamps.remove(index);
+                self.radii.remove(index);
+                self.weights.remove(index);
+                removed.push(id);
+            } else {
+                index += 1;
+            }
+        }
+
+        self.overflow &= self.points.len() > self.capacity();
+
+        let mut count = before - self.points.len();
+        for region in &mut self.regions {
+            count += region.remove_in_circle_inner(center, radius, removed);
+
+            if !region.regions.is_empty() && region.count() <= region.capacity() {
+                let unmerged = region.count();
+                region.merge_children();
+                count += unmerged - region.count();
+            }
+        }
+
+        self.subtree_count -= count;
+        count
+    }
+
+    // Pulls every entry of the subtree into this node and drops the children
+    fn merge_children(&mut self) {
+        for mut region in std::mem::take(&mut self.regions) {
+            region.merge_children();
+
+            let entries = region.stamps.into_iter().zip(region.radii).zip(region.weights);
+            for (entry, ((stamp, radius), weight)) in region.points.into_iter().zip(entries) {
+                // Circles stored in several leaves only need one copy once they share a leaf again
+                if radius > 0.0 && self.points.iter().zip(&self.radii).any(|(p, r)| p.0 == entry.0 && *r > 0.0) {
+                    continue;
+                }
+
+                self.points.push(entry);
+                self.stamps.push(stamp);
+                self.radii.push(radius);
+                self.weights.push(weight);
+            }
+        }
+
+        self.subtree_count = self.points.len();
+        self.overflow = false;
+    }
+
+    // Region of the leaf that owns `position`
+    pub fn leaf_region(&self, position: Vec2) -> Option<Rect> {
+        if !self.region.contains(position) {
+            return None;
+        }
+
+        if self.regions.is_empty() {
+            return Some(self.region);
+        }
+
+        self.regions.iter().find_map(|region| region.leaf_region(position))
+    }
+
+    pub fn nearest(&self, position: Vec2, metric: Metric) -> Option<(u32, Vec2)> {
+        self.k_nearest(position, 1, metric).pop()
+    }
+
+    // `nearest` skipping the points rejected by `pred`
+    pub fn nearest_where(&self, position: Vec2, metric: Metric, pred: impl Fn(u32, Vec2) -> bool) -> Option<(u32, Vec2)> {
+        let mut best = Vec::with_capacity(1);
+        self.k_nearest_in(position, 1, metric, &pred, &mut best);
+        best.pop().map(|(point, _)| point)
+    }
+
+    // Up to `k` distinct ids closest to `position`, nearest first
+    pub fn k_nearest(&self, position: Vec2, k: usize, metric: Metric) -> Vec<(u32, Vec2)> {
+        let mut best = Vec::with_capacity(k);
+
+        if k > 0 {
+            self.k_nearest_in(position, k, metric, &|_, _| true, &mut best);
+        }
+
+        best.into_iter().map(|(point, _)| point).collect()
+    }
+
+    // `best` stays sorted by distance, a node farther than the current k-th match can't improve it
+    fn k_nearest_in(&self, position: Vec2, k: usize, metric: Metric, pred: &impl Fn(u32, Vec2) -> bool, best: &mut Vec<((u32, Vec2), f32)>) {
+        if best.len() == k && metric.distance_to_rect(&self.region, position) > best[k - 1].1 {
+            return;
+        }
+
+        for point in self.points.iter().filter(|(id, point)| pred(*id, *point)) {
+            let distance = metric.distance(point.1, position);
+            let improves = best.len() < k || distance < best[k - 1].1;
+
+            // Circles are stored in every leaf they overlap, keep a single entry per id
+            if improves && !best.iter().any(|(other, _)| other.0 == point.0) {
+                let at = best.partition_point(|(_, other)| *other <= distance);
+                best.insert(at, (*point, distance));
+                best.truncate(k);
+            }
+        }
+
+        let mut regions: Vec<&QuadNode> = self.regions.iter().collect();
+        regions.sort_by(|a, b| {
+            metric.distance_to_rect(&a.region, position).total_cmp(&metric.distance_to_rect(&b.region, position))
+        });
+
+        for region in regions {
+            region.k_nearest_in(position, k, metric, pred, best);
+        }
+    }
+
+    // Moves a point without rebuilding, returns false and leaves the tree untouched if `id` was
+    // not found at `old`
+    pub fn update_position(&mut self, id: u32, old: &Vec2, new: &Vec2) -> bool {
+        let Some(weight) = self.remove_entry(id, old) else {
+            return false;
+        };
+
+        self.add_weighted(id, new, weight);
+        true
+    }
+
+    fn split(&mut self) {
+        self.regions = self.make_regions();
+        self.activity = 1.0;
+
+        let entries = self.stamps.iter().zip(&self.radii).zip(&self.weights);
+        for ((id, position), ((stamp, radius), weight)) in self.points.iter().zip(entries) {
+            for region in &mut self.regions {
+                region.add_entry(*id, position, *radius, *weight, *stamp);
+            }
+        }
+
+        // Circles spanning several children are stored once per child they overlap
+        self.subtree_count = self.regions.iter().map(|region| region.count()).sum();
+
+        self.points.clear();
+        self.stamps.clear();
+        self.radii.clear();
+        self.weights.clear();
+    }
+
+    // Uniform over stored entries, descends into each child with probability proportional
+    // to its cached count instead of collecting every point
+    pub fn random_point(&self, rng: &mut impl Rng) -> Option<(u32, Vec2)> {
+        if self.count() == 0 {
+            return None;
+        }
+
+        self.nth_point(rng.gen_range(0..self.count()))
+    }
+
+    fn nth_point(&self, mut index: usize) -> Option<(u32, Vec2)> {
+        if let Some(point) = self.points.get(index) {
+            return Some(*point);
+        }
+        index -= self.points.len();
+
+        for region in &self.regions {
+            if index < region.count() {
+                return region.nth_point(index);
+            }
+            index -= region.count();
+        }
+
+        None
+    }
+
+    pub fn activity(&self) -> f32 {
+        self.activity
+    }
+
+    pub fn decay_activity(&mut self, dt: f32) {
+        self.activity = decayed_activity(self.activity, ACTIVITY_DECAY_RATE, dt);
+
+        for region in &mut self.regions {
+            region.decay_activity(dt);
+        }
+    }
+
+    fn in_region(&self, query_area: &Rect) -> bool {
+        self.region.intersect(*query_area).is_some()
+    }
+
+    pub fn all_points(&self) -> Vec<(u32, Vec2)> {
+        let mut points = self.points.clone();
+
+        for region in &self.regions {
+            points.append(&mut region.all_points());
+        }
+
+        points
+    }
+
+    // Inserts every point of `other` into this tree, both trees must cover the same region
+    pub fn merge_from(&mut self, other: &QuadNode) {
+        assert_eq!(self.region, other.region, "merged quadtrees must share the same root region");
+
+        for (id, position) in other.all_points() {
+            self.add(id, &position);
+        }
+    }
+
+    // Tight bounds of every stored point, `None` when the tree is empty
+    pub fn points_aabb(&self) -> Option<Rect> {
+        let mut bounds: Option<(Vec2, Vec2)> = None;
+        self.extend_bounds(&mut bounds);
+
+        bounds.map(|(min, max)| Rect::new(min.x, min.y, max.x - min.x, max.y - min.y))
+    }
+
+    fn extend_bounds(&self, bounds: &mut Option<(Vec2, Vec2)>) {
+        for (_, position) in &self.points {
+            *bounds = match *bounds {
+                Some((min, max)) => Some((min.min(*position), max.max(*position))),
+                None => Some((*position, *position)),
+            };
+        }
+
+        for region in &self.regions {
+            region.extend_bounds(bounds);
+        }
+    }
+
+    // Bounds of the occupied nodes overlapping `query_area`, without descending past `max_depth`
+    pub fn coarse_query(&self, query_area: &Rect, max_depth: usize) -> Vec<Rect> {
+        let mut rects = Vec::new();
+
+        if !self.in_region(query_area) || self.count() == 0 {
+            return rects;
+        }
+
+        if self.depth >= max_depth || self.regions.is_empty() {
+            rects.push(self.region);
+            return rects;
+        }
+
+        for region in &self.regions {
+            rects.append(&mut region.coarse_query(query_area, max_depth));
+        }
+
+        rects
+    }
+
+    pub fn occupied_leaves(&self) -> Vec<Rect> {
+        if self.regions.is_empty() {
+            return if self.points.is_empty() { Vec::new() } else { vec![self.region] };
+        }
+
+        self.regions.iter().flat_map(|region| region.occupied_leaves()).collect()
+    }
+
+    pub fn leaves_in(&self, query_area: &Rect) -> Vec<Rect> {
+        if !self.in_region(query_area) {
+            return Vec::new();
+        }
+
+        if self.regions.is_empty() {
+            return vec![self.region];
+        }
+
+        self.regions.iter().flat_map(|node| node.leaves_in(query_area)).collect()
+    }
+
+    // Graphviz digraph of the subdivision, one node per region labelled with its bounds and count
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph quadtree {\n    node [shape=box];\n");
+        let mut next_id = 0;
+        self.write_dot(&mut dot, &mut next_id);
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn write_dot(&self, dot: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+
+        let r = self.region;
+        dot.push_str(&format!(
+            "    n{} [label=\"({}, {}) {}x{}\\ncount: {}\"];\n",
+            id, r.x, r.y, r.w, r.h, self.count()
+        ));
+
+        for region in &self.regions {
+            let child = region.write_dot(dot, next_id);
+            dot.push_str(&format!("    n{} -> n{};\n", id, child));
+        }
+
+        id
+    }
+
+    // Checks the structural invariants every operation is expected to keep, reporting the first
+    // violation found
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.regions.is_empty() {
+            if !self.points.is_empty() {
+                return Err(format!("internal node {:?} holds {} points", self.region, self.points.len()));
+            }
+
+            let expected = self.child_regions();
+            let actual: Vec<Rect> = self.regions.iter().map(|region| region.region).collect();
+            if actual != expected {
+                return Err(format!("children of {:?} do not tile it: {:?}", self.region, actual));
+            }
+
+            let children_count: usize = self.regions.iter().map(|region| region.count()).sum();
+            if self.subtree_count != children_count {
+                return Err(format!(
+                    "node {:?} caches a count of {} while its children hold {}",
+                    self.region, self.subtree_count, children_count
+                ));
+            }
+
+            return self.regions.iter().try_for_each(|region| region.validate());
+        }
+
+        if self.points.len() != self.stamps.len()
+            || self.points.len() != self.radii.len()
+            || self.points.len() != self.weights.len()
+        {
+            return Err(format!("leaf {:?} has mismatched entry arrays", self.region));
+        }
+
+        if self.subtree_count != self.points.len() {
+            return Err(format!(
+                "leaf {:?} caches a count of {} while it holds {} points",
+                self.region, self.subtree_count, self.points.len()
+            ));
+        }
+
+        for ((id, position), radius) in self.points.iter().zip(&self.radii) {
+            if !self.covers(position, *radius) {
+                return Err(format!("point {} at {:?} lies outside its leaf {:?}", id, position, self.region));
+            }
+        }
+
+        if self.points.len() > self.capacity() {
+            if self.can_split() {
+                return Err(format!(
+                    "leaf {:?} holds {} points over its limit of {} while it could still split",
+                    self.region, self.points.len(), self.capacity()
+                ));
+            }
+
+            if !self.overflow {
+                return Err(format!("leaf {:?} is over its limit without being in overflow mode", self.region));
+            }
+        }
+
+        if self.overflow && !self.points.is_sorted_by_key(|(id, _)| *id) {
+            return Err(format!("overflowing leaf {:?} is not sorted by id", self.region));
+        }
+
+        Ok(())
+    }
+
+    // Ids only in `other` are added, ids only in `self` removed, and ids stored in a different set
+    // of leaves moved, `self` being the earlier frame. Every list is sorted
+    pub fn diff(&self, other: &QuadNode) -> TreeDiff {
+        let before = self.leaves_by_id();
+        let after = other.leaves_by_id();
+
+        let mut diff = TreeDiff::default();
+        for (id, leaves) in &after {
+            match before.get(id) {
+                None => diff.added.push(*id),
+                Some(old) if old != leaves => diff.moved.push(*id),
+                Some(_) => {}
+            }
+        }
+        diff.removed = before.keys().filter(|id| !after.contains_key(id)).copied().collect();
+
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.moved.sort_unstable();
+        diff
+    }
+
+    fn leaves_by_id(&self) -> HashMap<u32, Vec<Rect>> {
+        let mut leaves: HashMap<u32, Vec<Rect>> = HashMap::new();
+        self.collect_leaves_by_id(&mut leaves);
+        leaves
+    }
+
+    fn collect_leaves_by_id(&self, leaves: &mut HashMap<u32, Vec<Rect>>) {
+        for (id, _) in &self.points {
+            leaves.entry(*id).or_default().push(self.region);
+        }
+
+        for region in &self.regions {
+            region.collect_leaves_by_id(leaves);
+        }
+    }
+
+    // Flags trees more than `DEGENERATE_DEPTH_RATIO` times deeper than a uniform spread of the
+    // same points would need, usually clustered or coincident points with a small limit
+    pub fn health(&self) -> TreeHealth {
+        let mut leaves = Vec::new();
+        self.leaf_stats(0, &mut leaves);
+
+        let max_depth = leaves.iter().map(|(depth, _)| *depth).max().unwrap_or(0);
+        let stored: usize = leaves.iter().map(|(_, points)| *points).sum();
+        let average_leaf_occupancy = stored as f32 / leaves.len() as f32;
+
+        let uniform_leaves = (self.count() as f32 / self.capacity().max(1) as f32).max(1.0);
+        let uniform_depth = uniform_leaves.log((self.branch * self.branch) as f32).ceil().max(1.0);
+
+        TreeHealth {
+            max_depth,
+            leaf_count: leaves.len(),
+            average_leaf_occupancy,
+            degenerate: max_depth as f32 > uniform_depth * DEGENERATE_DEPTH_RATIO,
+        }
+    }
+
+    // Stored points of every leaf in traversal order, for picking a `limit` from the histogram
+    pub fn leaf_occupancies(&self) -> Vec<usize> {
+        let mut leaves = Vec::new();
+        self.leaf_stats(0, &mut leaves);
+        leaves.into_iter().map(|(_, points)| points).collect()
+    }
+
+    // The `n` fullest leaves, most points first, ties kept in traversal order
+    pub fn densest_regions(&self, n: usize) -> Vec<(Rect, usize)> {
+        let mut leaves = Vec::new();
+        self.leaf_regions(&mut leaves);
+
+        let mut ranked: Vec<(usize, (Rect, usize))> = leaves.into_iter().enumerate().collect();
+        let by_density = |a: &(usize, (Rect, usize)), b: &(usize, (Rect, usize))| b.1.1.cmp(&a.1.1).then(a.0.cmp(&b.0));
+
+        if n < ranked.len() {
+            if n > 0 {
+                ranked.select_nth_unstable_by(n - 1, by_density);
+            }
+            ranked.truncate(n);
+        }
+        ranked.sort_by(by_density);
+
+        ranked.into_iter().map(|(_, leaf)| leaf).collect()
+    }
+
+    fn leaf_regions(&self, leaves: &mut Vec<(Rect, usize)>) {
+        if self.regions.is_empty() {
+            leaves.push((self.region, self.points.len()));
+            return;
+        }
+
+        for region in &self.regions {
+            region.leaf_regions(leaves);
+        }
+    }
+
+    // (depth below the starting node, stored points) of every leaf
+    fn leaf_stats(&self, depth: usize, leaves: &mut Vec<(usize, usize)>) {
+        if self.regions.is_empty() {
+            leaves.push((depth, self.points.len()));
+            return;
+        }
+
+        for region in &self.regions {
+            region.leaf_stats(depth + 1, leaves);
+        }
+    }
+
+    // Points per quadrant around `around`, ordered top left, top right, bottom left, bottom
+    // right. Points on an axis count towards the right/bottom side
+    pub fn quadrant_counts(&self, around: Vec2) -> [usize; 4] {
+        let mut counts = [0; 4];
+
+        for (_, position) in self.all_points() {
+            let right = (position.x >= around.x) as usize;
+            let bottom = (position.y >= around.y) as usize;
+            counts[bottom * 2 + right] += 1;
+        }
+
+        counts
+    }
+}
+
+// Exponential fade, activity halves every `ln 2 / rate` seconds
+pub fn decayed_activity(activity: f32, rate: f32, dt: f32) -> f32 {
+    activity * (-rate * dt).exp()
+}
+
+fn rect_covers(outer: &Rect, inner: &Rect) -> bool {
+    outer.left() <= inner.left() && outer.top() <= inner.top()
+        && outer.right() >= inner.right() && outer.bottom() >= inner.bottom()
+}
+
+// Per axis gap between `position` and the closest point of `rect`, zero on the axes it is inside
+fn gap_to_rect(rect: &Rect, position: Vec2) -> Vec2 {
+    Vec2::new(
+        (rect.left() - position.x).max(0.0).max(position.x - rect.right()),
+        (rect.top() - position.y).max(0.0).max(position.y - rect.bottom()),
+    )
+}
+
+fn distance_squared_to_rect(rect: &Rect, position: Vec2) -> f32 {
+    gap_to_rect(rect, position).length_squared()
+}
+
+// Distance used by the nearest neighbour queries
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Metric {
+    Euclidean,
+    Manhattan,
+    Chebyshev,
+}
+
+impl Metric {
+    pub fn distance(self, a: Vec2, b: Vec2) -> f32 {
+        self.length(a - b)
+    }
+
+    // Lower bound on the distance to any point inside `rect`
+    pub fn distance_to_rect(self, rect: &Rect, position: Vec2) -> f32 {
+        self.length(gap_to_rect(rect, position))
+    }
+
+    fn length(self, delta: Vec2) -> f32 {
+        match self {
+            Metric::Euclidean => delta.length(),
+            Metric::Manhattan => delta.x.abs() + delta.y.abs(),
+            Metric::Chebyshev => delta.x.abs().max(delta.y.abs()),
+        }
+    }
+}
+
+// Nodes and leaves overlapping the query area, including the root, and the points stored in them
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct QueryStats {
+    pub nodes_visited: usize,
+    pub leaves_visited: usize,
+    pub points_examined: usize,
+}
+
+pub struct VisitIter<'a> {
+    area: Rect,
+    stack: Vec<&'a QuadNode>,
+}
+
+impl<'a> Iterator for VisitIter<'a> {
+    type Item = (&'a QuadNode, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let overlaps = node.in_region(&self.area);
+
+        if overlaps {
+            self.stack.extend(node.regions.iter().rev());
+        }
+
+        Some((node, overlaps))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TreeHealth {
+    pub max_depth: usize,
+    pub leaf_count: usize,
+    pub average_leaf_occupancy: f32,
+    pub degenerate: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TreeDiff {
+    pub added: Vec<u32>,
+    pub removed: Vec<u32>,
+    pub moved: Vec<u32>,
+}
+
+// Depth first walk over the nodes overlapping `area`, children are pushed in reverse so they
+// pop in the same order `query` visits them
+pub struct QueryIter<'a> {
+    area: Rect,
+    stack: Vec<&'a QuadNode>,
+    points: std::slice::Iter<'a, (u32, Vec2)>,
+}
+
+impl Iterator for QueryIter<'_> {
+    type Item = (u32, Vec2);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(point) = self.points.next() {
+                return Some(*point);
+            }
+
+            let node = self.stack.pop()?;
+            if !node.in_region(&self.area) {
+                continue;
+            }
+
+            if node.regions.is_empty() {
+                self.points = node.points.iter();
+            } else {
+                self.stack.extend(node.regions.iter().rev());
+            }
+        }
+    }
+}
+
+// Uniform grid broad phase, used as a baseline to compare the quadtree against
+pub struct SpatialGrid {
+    region: Rect,
+    cell_size: f32,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<(u32, Vec2)>>,
+}
+
+impl SpatialGrid {
+    pub fn new(region: Rect, cell_size: f32) -> Self {
+        let cols = (region.w / cell_size).ceil() as usize;
+        let rows = (region.h / cell_size).ceil() as usize;
+
+        Self {
+            region,
+            cell_size,
+            cols,
+            rows,
+            cells: vec![Vec::new(); cols * rows],
+        }
+    }
+
+    fn cell_coords(&self, position: Vec2) -> (usize, usize) {
+        let col = ((position.x - self.region.x) / self.cell_size).floor().max(0.0) as usize;
+        let row = ((position.y - self.region.y) / self.cell_size).floor().max(0.0) as usize;
+
+        (col.min(self.cols - 1), row.min(self.rows - 1))
+    }
+
+    fn cell_range(&self, query_area: &Rect) -> Option<((usize, usize), (usize, usize))> {
+        let area = self.region.intersect(*query_area)?;
+        let min = self.cell_coords(area.point());
+        let max = self.cell_coords(area.point() + area.size());
+
+        Some((min, max))
+    }
+}
+
+pub trait BroadPhase {
+    fn insert(&mut self, id: u32, position: &Vec2);
+    fn candidates(&self, query_area: &Rect) -> Vec<(u32, Vec2)>;
+    fn candidate_regions(&self, query_area: &Rect) -> Vec<Rect>;
+}
+
+impl BroadPhase for QuadNode {
+    fn insert(&mut self, id: u32, position: &Vec2) {
+        self.add(id, position);
+    }
+
+    fn candidates(&self, query_area: &Rect) -> Vec<(u32, Vec2)> {
+        self.query(query_area)
+    }
+
+    fn candidate_regions(&self, query_area: &Rect) -> Vec<Rect> {
+        self.leaves_in(query_area)
+    }
+}
+
+impl BroadPhase for SpatialGrid {
+    fn insert(&mut self, id: u32, position: &Vec2) {
+        if !self.region.contains(*position) {
+            return;
+        }
+
+        let (col, row) = self.cell_coords(*position);
+        self.cells[row * self.cols + col].push((id, *position));
+    }
+
+    fn candidates(&self, query_area: &Rect) -> Vec<(u32, Vec2)> {
+        let mut found = Vec::new();
+
+        if let Some(((min_col, min_row), (max_col, max_row))) = self.cell_range(query_area) {
+            for row in min_row..=max_row {
+                for col in min_col..=max_col {
+                    found.extend_from_slice(&self.cells[row * self.cols + col]);
+                }
+            }
+        }
+
+        found
+    }
+
+    fn candidate_regions(&self, query_area: &Rect) -> Vec<Rect> {
+        let mut regions = Vec::new();
+
+        if let Some(((min_col, min_row), (max_col, max_row))) = self.cell_range(query_area) {
+            for row in min_row..=max_row {
+                for col in min_col..=max_col {
+                    regions.push(Rect::new(
+                        self.region.x + col as f32 * self.cell_size,
+                        self.region.y + row as f32 * self.cell_size,
+                        self.cell_size,
+                        self.cell_size,
+                    ));
+                }
+            }
+        }
+
+        regions
+    }
+}
+
+// Number of candidates each broad phase hands to the narrow phase for the same query
+pub struct BroadPhaseComparison {
+    pub quadtree_candidates: usize,
+    pub grid_candidates: usize,
+}
+
+pub fn compare_broad_phases(quadtree: &dyn BroadPhase, grid: &dyn BroadPhase, query_area: &Rect) -> BroadPhaseComparison {
+    BroadPhaseComparison {
+        quadtree_candidates: quadtree.candidates(query_area).len(),
+        grid_candidates: grid.candidates(query_area).len(),
+    }
+}
+
+// Integer rect, half open like `Rect::contains` so every cell belongs to exactly one node
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IRect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl IRect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn contains(&self, point: IVec2) -> bool {
+        point.x >= self.x && point.x < self.x + self.w && point.y >= self.y && point.y < self.y + self.h
+    }
+
+    pub fn overlaps(&self, other: &IRect) -> bool {
+        self.x < other.x + other.w && other.x < self.x + self.w && self.y < other.y + other.h && other.y < self.y + self.h
+    }
+}
+
+// Point quadtree over integer grid coordinates, kept separate from `QuadNode` since it has no
+// use for radii, generations or limit policies. Odd sizes split into `w / 2` and `w - w / 2`
+// so the children tile the parent without gaps
+pub struct QuadNodeI {
+    limit: usize,
+    depth: usize,
+    max_depth: usize,
+    pub region: IRect,
+    points: Vec<(u32, IVec2)>,
+    regions: Vec<QuadNodeI>,
+}
+
+impl QuadNodeI {
+    pub fn new(region: IRect, limit: usize) -> Self {
+        Self {
+            limit,
+            depth: 0,
+            max_depth: QUADTREE_MAX_DEPTH,
+            region,
+            points: Vec::new(),
+            regions: Vec::new(),
+        }
+    }
+
+    fn make_regions(&self) -> Vec<QuadNodeI> {
+        let IRect { x, y, w, h } = self.region;
+        let (lw, lh) = (w / 2, h / 2);
+
+        [
+            IRect::new(x, y, lw, lh),
+            IRect::new(x + lw, y, w - lw, lh),
+            IRect::new(x, y + lh, lw, h - lh),
+            IRect::new(x + lw, y + lh, w - lw, h - lh),
+        ]
+        .into_iter()
+        .map(|region| {
+            QuadNodeI {
+                limit: self.limit,
+                depth: self.depth + 1,
+                max_depth: self.max_depth,
+                region,
+                points: Vec::new(),
+                regions: Vec::new(),
+            }
+        })
+        .collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.points.len() + self.regions.iter().map(|region| region.count()).sum::<usize>()
+    }
+
+    // Returns false if `position` lies outside the tree
+    pub fn add(&mut self, id: u32, position: IVec2) -> bool {
+        if !self.region.contains(position) {
+            return false;
+        }
+
+        if self.regions.is_empty() {
+            let splittable = self.region.w > 1 || self.region.h > 1;
+            if self.points.len() < self.limit || self.depth >= self.max_depth || !splittable {
+                self.points.push((id, position));
+                return true;
+            }
+
+            self.regions = self.make_regions();
+            for (point_id, point) in std::mem::take(&mut self.points) {
+                self.add(point_id, point);
+            }
+        }
+
+        self.regions.iter_mut().any(|region| region.add(id, position))
+    }
+
+    // Every point inside `area`, filtered exactly rather than returning whole leaves
+    pub fn query(&self, area: &IRect) -> Vec<(u32, IVec2)> {
+        let mut points = Vec::new();
+        self.collect(area, &mut points);
+        points
+    }
+
+    fn collect(&self, area: &IRect, points: &mut Vec<(u32, IVec2)>) {
+        if !self.region.overlaps(area) {
+            return;
+        }
+
+        points.extend(self.points.iter().filter(|(_, position)| area.contains(*position)));
+
+        for region in &self.regions {
+            region.collect(area, points);
+        }
+    }
+
+    // Region of the leaf that owns `position`
+    pub fn leaf_region(&self, position: IVec2) -> Option<IRect> {
+        if !self.region.contains(position) {
+            return None;
+        }
+
+        if self.regions.is_empty() {
+            return Some(self.region);
+        }
+
+        self.regions.iter().find_map(|region| region.leaf_region(position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn region() -> Rect {
+        Rect::new(0.0, 0.0, 100.0, 100.0)
+    }
+
+    fn scattered(count: u32, seed: u64) -> Vec<(u32, Vec2)> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        (0..count).map(|id| (id, Vec2::new(rng.gen_range(0.0..100.0), rng.gen_range(0.0..100.0)))).collect()
+    }
+
+    fn tree_of(points: &[(u32, Vec2)], limit: usize) -> QuadNode {
+        let mut tree = QuadNode::with_root(region(), limit);
+        for (id, position) in points {
+            tree.add(*id, position);
+        }
+        tree
+    }
+
+    fn sorted_ids(points: Vec<(u32, Vec2)>) -> Vec<u32> {
+        let mut ids: Vec<u32> = points.into_iter().map(|(id, _)| id).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn recomputed_count(tree: &QuadNode) -> usize {
+        tree.leaf_occupancies().iter().sum()
+    }
+
+    #[test]
+    fn cached_count_matches_leaves() {
+        let mut tree = QuadNode::with_root(region(), 1);
+        tree.add_circle(0, &Vec2::new(50.0, 50.0), 5.0);
+        tree.add(1, &Vec2::new(10.0, 10.0));
+        tree.add(2, &Vec2::new(90.0, 10.0));
+        tree.add_circle(3, &Vec2::new(20.0, 80.0), 10.0);
+
+        assert_eq!(tree.count(), recomputed_count(&tree));
+
+        tree.remove_in_circle(Vec2::new(50.0, 50.0), 1.0);
+        assert_eq!(tree.count(), recomputed_count(&tree));
+
+        tree.clear();
+        assert_eq!(tree.count(), 0);
+        assert_eq!(recomputed_count(&tree), 0);
+
+        tree.add(4, &Vec2::new(60.0, 60.0));
+        assert_eq!(tree.count(), recomputed_count(&tree));
+    }
+
+    #[test]
+    fn split_counts_circles_once_per_child() {
+        let mut tree = QuadNode::with_root(region(), 1);
+        tree.add_circle(0, &Vec2::new(50.0, 50.0), 5.0);
+        tree.add(1, &Vec2::new(10.0, 10.0));
+
+        assert_eq!(tree.count(), recomputed_count(&tree));
+        assert_eq!(tree.remove_in_circle(Vec2::new(50.0, 50.0), 1.0), vec![0]);
+        assert_eq!(tree.count(), recomputed_count(&tree));
+    }
+
+    #[test]
+    fn update_position_keeps_one_entry() {
+        let mut tree = QuadNode::with_root(region(), 1);
+        let old = Vec2::new(10.0, 10.0);
+        let new = Vec2::new(90.0, 90.0);
+        tree.add(0, &old);
+        tree.add(1, &Vec2::new(50.0, 20.0));
+
+        assert!(tree.update_position(0, &old, &new));
+        let entries: Vec<(u32, Vec2)> = tree.all_points().into_iter().filter(|(id, _)| *id == 0).collect();
+        assert_eq!(entries, vec![(0, new)]);
+        assert_eq!(tree.count(), 2);
+    }
+
+    #[test]
+    fn update_position_ignores_missing_entries() {
+        let mut tree = QuadNode::with_root(region(), 1);
+        tree.add(0, &Vec2::new(10.0, 10.0));
+        tree.add(1, &Vec2::new(90.0, 10.0));
+
+        // Unknown id, and a known id looked up in a leaf it isn't stored in
+        assert!(!tree.update_position(7, &Vec2::new(30.0, 30.0), &Vec2::new(60.0, 60.0)));
+        assert!(!tree.update_position(0, &Vec2::new(80.0, 80.0), &Vec2::new(60.0, 60.0)));
+
+        assert_eq!(tree.all_points(), vec![(0, Vec2::new(10.0, 10.0)), (1, Vec2::new(90.0, 10.0))]);
+    }
+
+    fn split_tree() -> QuadNode {
+        let mut tree = QuadNode::with_root(region(), 2);
+        for (i, position) in [(10.0, 10.0), (20.0, 30.0), (70.0, 10.0), (80.0, 80.0), (30.0, 70.0)].into_iter().enumerate() {
+            tree.add(i as u32, &Vec2::new(position.0, position.1));
+        }
+        tree.add_circle(5, &Vec2::new(50.0, 50.0), 10.0);
+
+        tree
+    }
+
+    #[test]
+    fn validate_accepts_built_tree() {
+        let mut tree = split_tree();
+        assert_eq!(tree.validate(), Ok(()));
+
+        tree.remove(0, &Vec2::new(10.0, 10.0));
+        tree.remove_in_circle(Vec2::new(50.0, 50.0), 1.0);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_corrupted_tree() {
+        let mut tree = split_tree();
+        tree.subtree_count += 1;
+        assert!(tree.validate().is_err());
+
+        let mut tree = split_tree();
+        tree.regions[0].subtree_count -= 1;
+        assert!(tree.validate().is_err());
+
+        let mut tree = split_tree();
+        let leaf = tree.regions.iter_mut().find(|region| region.regions.is_empty() && !region.points.is_empty()).unwrap();
+        leaf.points[0].1 = leaf.region.point() + leaf.region.size() * 1.5;
+        assert!(tree.validate().is_err());
+
+        let mut tree = split_tree();
+        tree.points.push((9, Vec2::new(10.0, 10.0)));
+        assert!(tree.validate().is_err());
+    }
+
+    #[test]
+    fn query_outside_root_visits_nothing() {
+        let tree = split_tree();
+        let outside = Rect::new(200.0, -50.0, 30.0, 30.0);
+
+        assert!(tree.query(&outside).is_empty());
+        let (found, stats) = tree.query_with_stats(&outside);
+        assert!(found.is_empty());
+        assert_eq!(stats.nodes_visited, 0);
+        assert_eq!(stats.points_examined, 0);
+    }
+
+    #[test]
+    fn any_in_circle_match_and_none() {
+        let tree = split_tree();
+
+        assert_eq!(tree.any_in_circle(Vec2::new(12.0, 12.0), 5.0, |_, _| true), Some((0, Vec2::new(10.0, 10.0))));
+        assert_eq!(tree.any_in_circle(Vec2::new(12.0, 12.0), 5.0, |id, _| id != 0), None);
+        assert_eq!(tree.any_in_circle(Vec2::new(90.0, 40.0), 5.0, |_, _| true), None);
+    }
+
+    #[test]
+    fn any_in_circle_stops_early() {
+        let mut tree = QuadNode::with_root(region(), 4);
+        for i in 0..400 {
+            tree.add(i, &Vec2::new((i % 20) as f32 * 5.0 + 2.5, (i / 20) as f32 * 5.0 + 2.5));
+        }
+
+        let examined = std::cell::Cell::new(0);
+        let found = tree.any_in_circle(Vec2::new(50.0, 50.0), 30.0, |_, _| {
+            examined.set(examined.get() + 1);
+            true
+        });
+
+        assert!(found.is_some());
+        assert_eq!(examined.get(), 1);
+        assert!(tree.query_circle(Vec2::new(50.0, 50.0), 30.0).len() > 100);
+    }
+
+    #[test]
+    fn concurrent_queries_on_shared_tree() {
+        let mut tree = QuadNode::with_root(region(), 4);
+        for i in 0..400 {
+            tree.add(i, &Vec2::new((i % 20) as f32 * 5.0 + 2.5, (i / 20) as f32 * 5.0 + 2.5));
+        }
+        let tree = Arc::new(tree);
+
+        let workers: Vec<_> = (0..4)
+            .map(|quadrant| {
+                let tree = Arc::clone(&tree);
+                std::thread::spawn(move || {
+                    let center = Vec2::new((quadrant % 2) as f32 * 50.0 + 25.0, (quadrant / 2) as f32 * 50.0 + 25.0);
+                    let mut found: Vec<u32> = tree.query_circle(center, 10.0).into_iter().map(|(id, _)| id).collect();
+                    found.sort_unstable();
+                    (center, found)
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            let (center, found) = worker.join().unwrap();
+            let mut expected: Vec<u32> = tree.all_points().into_iter()
+                .filter(|(_, position)| position.distance(center) <= 10.0)
+                .map(|(id, _)| id)
+                .collect();
+            expected.sort_unstable();
+
+            assert!(!found.is_empty());
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn broad_phase_candidates_on_fixed_scene() {
+        let mut quadtree = QuadNode::with_root(region(), 2);
+        let mut grid = SpatialGrid::new(region(), 50.0);
+        let scene = [(10.0, 10.0), (20.0, 20.0), (60.0, 10.0), (90.0, 90.0), (40.0, 40.0), (30.0, 5.0)];
+
+        for (i, (x, y)) in scene.into_iter().enumerate() {
+            quadtree.insert(i as u32, &Vec2::new(x, y));
+            grid.insert(i as u32, &Vec2::new(x, y));
+        }
+
+        // The quadtree narrows down to the 25x25 leaf holding the first two points, the grid
+        // hands back its whole top left 50x50 cell
+        let comparison = compare_broad_phases(&quadtree, &grid, &Rect::new(5.0, 5.0, 10.0, 10.0));
+        assert_eq!(comparison.quadtree_candidates, 2);
+        assert_eq!(comparison.grid_candidates, 4);
+
+        let comparison = compare_broad_phases(&quadtree, &grid, &Rect::new(55.0, 55.0, 40.0, 40.0));
+        assert_eq!(comparison.quadtree_candidates, 1);
+        assert_eq!(comparison.grid_candidates, 1);
+    }
+
+    #[test]
+    fn query_current_after_clear() {
+        let mut tree = split_tree();
+        assert_eq!(tree.query_current(&region()).len(), tree.query(&region()).len());
+
+        tree.clear();
+        assert!(tree.query_current(&region()).is_empty());
+
+        tree.add(9, &Vec2::new(40.0, 60.0));
+        assert_eq!(tree.query_current(&region()), vec![(9, Vec2::new(40.0, 60.0))]);
+    }
+
+    #[test]
+    fn add_query_remove() {
+        let mut tree = QuadNode::with_root(region(), 2);
+        for i in 0..10 {
+            tree.add(i, &Vec2::new(i as f32 * 10.0 + 5.0, 50.0));
+        }
+        assert_eq!(tree.count(), 10);
+        assert!(tree.node_count() > 1);
+
+        let all: Vec<u32> = tree.query(&region()).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(all.len(), 10);
+
+        assert!(tree.remove(3, &Vec2::new(35.0, 50.0)));
+        assert!(!tree.remove(3, &Vec2::new(35.0, 50.0)));
+        assert_eq!(tree.count(), 9);
+        assert!(tree.query(&region()).iter().all(|(id, _)| *id != 3));
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn query_sorted_by_distance_then_id() {
+        let mut tree = QuadNode::with_root(region(), 2);
+        tree.add(4, &Vec2::new(60.0, 50.0));
+        tree.add(1, &Vec2::new(80.0, 50.0));
+        tree.add(3, &Vec2::new(40.0, 50.0));
+        tree.add(2, &Vec2::new(50.0, 55.0));
+
+        let ids: Vec<u32> = tree.query_sorted(&region(), Vec2::new(50.0, 50.0)).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![2, 3, 4, 1]);
+    }
+
+    #[test]
+    fn points_aabb_extremes() {
+        assert_eq!(QuadNode::with_root(region(), 2).points_aabb(), None);
+
+        let tree = split_tree();
+        assert_eq!(tree.points_aabb(), Some(Rect::new(10.0, 10.0, 70.0, 70.0)));
+
+        let mut tree = QuadNode::with_root(region(), 2);
+        tree.add(0, &Vec2::new(30.0, 40.0));
+        assert_eq!(tree.points_aabb(), Some(Rect::new(30.0, 40.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn query_obb_matches_brute_force() {
+        let points = scattered(500, 1);
+        let tree = tree_of(&points, 4);
+        let center = Vec2::new(45.0, 55.0);
+        let half_extents = Vec2::new(30.0, 8.0);
+
+        for step in 0..8 {
+            let angle = step as f32 * std::f32::consts::PI / 8.0;
+            let axis = Vec2::new(angle.cos(), angle.sin());
+            let normal = Vec2::new(-axis.y, axis.x);
+
+            let expected: Vec<(u32, Vec2)> = points.iter()
+                .filter(|(_, position)| {
+                    let d = *position - center;
+                    d.dot(axis).abs() <= half_extents.x && d.dot(normal).abs() <= half_extents.y
+                })
+                .copied()
+                .collect();
+
+            assert!(!expected.is_empty());
+            assert_eq!(sorted_ids(tree.query_obb(center, half_extents, angle)), sorted_ids(expected), "angle {}", angle);
+        }
+    }
+
+    #[test]
+    fn merge_equals_single_tree() {
+        let points = scattered(300, 2);
+        let (left, right) = points.split_at(120);
+
+        let mut merged = tree_of(left, 4);
+        merged.merge_from(&tree_of(right, 4));
+        let single = tree_of(&points, 4);
+
+        assert_eq!(merged.count(), single.count());
+        assert_eq!(sorted_ids(merged.all_points()), sorted_ids(single.all_points()));
+        let area = Rect::new(20.0, 30.0, 40.0, 25.0);
+        assert_eq!(sorted_ids(merged.query_circle(Vec2::new(50.0, 50.0), 20.0)), sorted_ids(single.query_circle(Vec2::new(50.0, 50.0), 20.0)));
+        assert_eq!(merged.count_in(&area), single.count_in(&area));
+        assert_eq!(merged.validate(), Ok(()));
+    }
+
+    #[test]
+    fn limit_policy_fewer_nodes_when_clustered() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let clustered: Vec<(u32, Vec2)> = (0..400)
+            .map(|id| (id, Vec2::new(rng.gen_range(70.0..74.0), rng.gen_range(20.0..24.0))))
+            .collect();
+
+        let fixed = tree_of(&clustered, 4);
+        let mut growing = QuadNode::with_limit_policy(region(), Arc::new(|depth| 4 << depth));
+        for (id, position) in &clustered {
+            growing.add(*id, position);
+        }
+
+        assert_eq!(growing.count(), fixed.count());
+        assert!(growing.node_count() < fixed.node_count(), "{} vs {}", growing.node_count(), fixed.node_count());
+        assert_eq!(growing.validate(), Ok(()));
+    }
+
+    #[test]
+    fn coarse_query_depth_one_gives_quadrants() {
+        let mut tree = QuadNode::with_root(region(), 1);
+        for (i, (x, y)) in [(10.0, 10.0), (30.0, 30.0), (20.0, 40.0), (90.0, 10.0), (80.0, 70.0)].into_iter().enumerate() {
+            tree.add(i as u32, &Vec2::new(x, y));
+        }
+
+        // The bottom left quadrant is empty, the top left one splits deeper but is reported whole
+        assert_eq!(
+            tree.coarse_query(&region(), 1),
+            vec![Rect::new(0.0, 0.0, 50.0, 50.0), Rect::new(50.0, 0.0, 50.0, 50.0), Rect::new(50.0, 50.0, 50.0, 50.0)]
+        );
+        assert_eq!(tree.coarse_query(&region(), 0), vec![region()]);
+    }
+
+    #[test]
+    fn occupied_leaves_on_known_layout() {
+        let mut tree = QuadNode::with_root(region(), 1);
+        tree.add(0, &Vec2::new(10.0, 10.0));
+        tree.add(1, &Vec2::new(90.0, 10.0));
+        tree.add(2, &Vec2::new(90.0, 90.0));
+        assert_eq!(
+            tree.occupied_leaves(),
+            vec![Rect::new(0.0, 0.0, 50.0, 50.0), Rect::new(50.0, 0.0, 50.0, 50.0), Rect::new(50.0, 50.0, 50.0, 50.0)]
+        );
+
+        tree.add(3, &Vec2::new(30.0, 30.0));
+        assert_eq!(
+            tree.occupied_leaves(),
+            vec![
+                Rect::new(0.0, 0.0, 25.0, 25.0),
+                Rect::new(25.0, 25.0, 25.0, 25.0),
+                Rect::new(50.0, 0.0, 50.0, 50.0),
+                Rect::new(50.0, 50.0, 50.0, 50.0),
+            ]
+        );
+        assert!(QuadNode::with_root(region(), 1).occupied_leaves().is_empty());
+    }
+
+    #[test]
+    fn query_ellipse_matches_brute_force() {
+        let points = scattered(500, 4);
+        let tree = tree_of(&points, 4);
+        let center = Vec2::new(50.0, 45.0);
+
+        for (rx, ry) in [(20.0, 19.0), (45.0, 3.0), (2.5, 40.0)] {
+            let expected: Vec<(u32, Vec2)> = points.iter()
+                .filter(|(_, position)| {
+                    let d = *position - center;
+                    (d.x / rx).powi(2) + (d.y / ry).powi(2) <= 1.0
+                })
+                .copied()
+                .collect();
+
+            assert!(!expected.is_empty());
+            assert_eq!(sorted_ids(tree.query_ellipse(center, rx, ry)), sorted_ids(expected), "rx {} ry {}", rx, ry);
+        }
+    }
+
+    #[test]
+    fn query_iter_equals_query() {
+        let tree = tree_of(&scattered(300, 5), 4);
+
+        for area in [region(), Rect::new(10.0, 20.0, 30.0, 15.0), Rect::new(60.0, 60.0, 0.5, 0.5), Rect::new(200.0, 0.0, 5.0, 5.0)] {
+            let collected: Vec<(u32, Vec2)> = tree.query_iter(&area).collect();
+            assert_eq!(sorted_ids(collected), sorted_ids(tree.query(&area)));
+        }
+    }
+
+    #[test]
+    fn quadrant_counts_per_quadrant_and_on_axis() {
+        let mut tree = QuadNode::with_root(region(), 2);
+        let points = [(10.0, 10.0), (20.0, 30.0), (70.0, 10.0), (30.0, 80.0), (60.0, 60.0), (90.0, 95.0), (80.0, 70.0)];
+        for (i, (x, y)) in points.into_iter().enumerate() {
+            tree.add(i as u32, &Vec2::new(x, y));
+        }
+        assert_eq!(tree.quadrant_counts(Vec2::new(50.0, 50.0)), [2, 1, 1, 3]);
+
+        // Points right on an axis count toward the right and bottom quadrants
+        let mut tree = QuadNode::with_root(region(), 2);
+        tree.add(0, &Vec2::new(50.0, 20.0));
+        tree.add(1, &Vec2::new(20.0, 50.0));
+        tree.add(2, &Vec2::new(50.0, 50.0));
+        assert_eq!(tree.quadrant_counts(Vec2::new(50.0, 50.0)), [0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn health_balanced_and_clustered() {
+        let balanced: Vec<(u32, Vec2)> = (0..256)
+            .map(|i| (i, Vec2::new((i % 16) as f32 * 6.25 + 3.0, (i / 16) as f32 * 6.25 + 3.0)))
+            .collect();
+        let health = tree_of(&balanced, 4).health();
+        assert!(!health.degenerate);
+        assert_eq!(health.max_depth, 3);
+        assert_eq!(health.leaf_count, 64);
+
+        let clustered: Vec<(u32, Vec2)> = (0..256)
+            .map(|i| (i, Vec2::new(70.0 + (i % 16) as f32 * 0.01, 20.0 + (i / 16) as f32 * 0.01)))
+            .collect();
+        let health = tree_of(&clustered, 4).health();
+        assert!(health.degenerate, "max depth {}", health.max_depth);
+    }
+
+    #[test]
+    fn random_point_uniform_over_entries() {
+        // Most points crowd one corner so the leaves hold very different counts
+        let mut points: Vec<(u32, Vec2)> = (0..7).map(|i| (i, Vec2::new(5.0 + i as f32, 5.0))).collect();
+        points.extend([(7, Vec2::new(80.0, 10.0)), (8, Vec2::new(20.0, 80.0)), (9, Vec2::new(90.0, 90.0))]);
+        let tree = tree_of(&points, 2);
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(6);
+        let mut hits = [0usize; 10];
+        for _ in 0..20_000 {
+            let (id, _) = tree.random_point(&mut rng).unwrap();
+            hits[id as usize] += 1;
+        }
+
+        for (id, count) in hits.iter().enumerate() {
+            assert!((1800..=2200).contains(count), "id {} drawn {} times", id, count);
+        }
+        assert_eq!(QuadNode::with_root(region(), 2).random_point(&mut rng), None);
+    }
+
+    #[test]
+    fn integer_tree_boundary_ownership() {
+        let mut tree = QuadNodeI::new(IRect::new(0, 0, 11, 7), 1);
+        assert!(tree.add(0, IVec2::new(0, 0)));
+        assert!(tree.add(1, IVec2::new(10, 6)));
+        assert!(!tree.add(2, IVec2::new(11, 0)));
+        assert!(!tree.add(3, IVec2::new(0, 7)));
+        assert_eq!(tree.count(), 2);
+
+        // Odd sizes split 5 + 6 wide and 3 + 4 tall, each cell belongs to exactly one child
+        assert_eq!(tree.leaf_region(IVec2::new(4, 2)), Some(IRect::new(0, 0, 5, 3)));
+        assert_eq!(tree.leaf_region(IVec2::new(5, 2)), Some(IRect::new(5, 0, 6, 3)));
+        assert_eq!(tree.leaf_region(IVec2::new(4, 3)), Some(IRect::new(0, 3, 5, 4)));
+        assert_eq!(tree.leaf_region(IVec2::new(5, 3)), Some(IRect::new(5, 3, 6, 4)));
+        assert_eq!(tree.leaf_region(IVec2::new(11, 3)), None);
+
+        assert!(tree.add(4, IVec2::new(5, 3)));
+        assert_eq!(tree.query(&IRect::new(5, 3, 1, 1)), vec![(4, IVec2::new(5, 3))]);
+        assert!(tree.query(&IRect::new(1, 1, 4, 2)).is_empty());
+        assert_eq!(tree.query(&IRect::new(0, 0, 11, 7)).len(), 3);
+    }
+
+    #[test]
+    fn query_stats_hand_counted() {
+        // Root split into quadrants, the top left one split once more
+        let mut tree = QuadNode::with_root(region(), 1);
+        for (i, (x, y)) in [(10.0, 10.0), (90.0, 10.0), (90.0, 90.0), (30.0, 30.0)].into_iter().enumerate() {
+            tree.add(i as u32, &Vec2::new(x, y));
+        }
+
+        let (found, stats) = tree.query_with_stats(&Rect::new(5.0, 5.0, 10.0, 10.0));
+        assert_eq!(found, vec![(0, Vec2::new(10.0, 10.0))]);
+        assert_eq!(stats, QueryStats { nodes_visited: 3, leaves_visited: 1, points_examined: 1 });
+
+        let (found, stats) = tree.query_with_stats(&Rect::new(20.0, 20.0, 40.0, 40.0));
+        assert_eq!(found.len(), 4);
+        assert_eq!(stats, QueryStats { nodes_visited: 9, leaves_visited: 7, points_examined: 4 });
+    }
+
+    #[test]
+    fn to_dot_node_and_edge_counts() {
+        let mut tree = QuadNode::with_root(region(), 1);
+        for (i, (x, y)) in [(10.0, 10.0), (90.0, 10.0), (90.0, 90.0), (30.0, 30.0)].into_iter().enumerate() {
+            tree.add(i as u32, &Vec2::new(x, y));
+        }
+
+        let dot = tree.to_dot();
+        assert!(dot.starts_with("digraph quadtree {"));
+        assert_eq!(dot.matches("[label=").count(), tree.node_count());
+        assert_eq!(dot.matches("[label=").count(), 9);
+        assert_eq!(dot.matches(" -> ").count(), 8);
+        assert!(dot.contains("n0 [label=\"(0, 0) 100x100\\ncount: 4\"]"));
+    }
+
+    #[test]
+    fn visits_in_depth_first_order() {
+        let mut tree = QuadNode::with_root(region(), 1);
+        for (i, (x, y)) in [(10.0, 10.0), (90.0, 10.0), (90.0, 90.0), (30.0, 30.0)].into_iter().enumerate() {
+            tree.add(i as u32, &Vec2::new(x, y));
+        }
+
+        let visits: Vec<(Rect, bool)> = tree.visits(&Rect::new(5.0, 5.0, 10.0, 10.0))
+            .map(|(node, overlaps)| (node.region, overlaps))
+            .collect();
+
+        assert_eq!(visits, vec![
+            (region(), true),
+            (Rect::new(0.0, 0.0, 50.0, 50.0), true),
+            (Rect::new(0.0, 0.0, 25.0, 25.0), true),
+            (Rect::new(25.0, 0.0, 25.0, 25.0), false),
+            (Rect::new(0.0, 25.0, 25.0, 25.0), false),
+            (Rect::new(25.0, 25.0, 25.0, 25.0), false),
+            (Rect::new(50.0, 0.0, 50.0, 50.0), false),
+            (Rect::new(0.0, 50.0, 50.0, 50.0), false),
+            (Rect::new(50.0, 50.0, 50.0, 50.0), false),
+        ]);
+    }
+
+    #[test]
+    fn query_where_subset_of_query() {
+        let tree = tree_of(&scattered(200, 7), 4);
+        let area = Rect::new(10.0, 10.0, 60.0, 50.0);
+
+        let all = tree.query(&area);
+        let even = tree.query_where(&area, |id, _| id % 2 == 0);
+        assert!(!even.is_empty());
+        assert!(even.iter().all(|(id, _)| id % 2 == 0));
+        assert_eq!(even, all.iter().filter(|(id, _)| id % 2 == 0).copied().collect::<Vec<_>>());
+
+        assert!(tree.query_where(&area, |_, _| false).is_empty());
+    }
+
+    #[test]
+    fn leaf_occupancies_per_leaf_and_sum() {
+        let mut tree = QuadNode::with_root(region(), 1);
+        for (i, (x, y)) in [(10.0, 10.0), (90.0, 10.0), (90.0, 90.0), (30.0, 30.0)].into_iter().enumerate() {
+            tree.add(i as u32, &Vec2::new(x, y));
+        }
+
+        // Top left split into four, then the other three quadrants, in child order
+        assert_eq!(tree.leaf_occupancies(), vec![1, 0, 0, 1, 1, 0, 1]);
+        assert_eq!(tree.leaf_occupancies().iter().sum::<usize>(), tree.count());
+
+        let tree = tree_of(&scattered(250, 8), 3);
+        assert_eq!(tree.leaf_occupancies().iter().sum::<usize>(), 250);
+        assert!(tree.leaf_occupancies().iter().all(|&count| count <= 3));
+    }
+
+    #[test]
+    fn remove_in_circle_inside_and_outside() {
+        let points = scattered(300, 9);
+        let mut tree = tree_of(&points, 4);
+        let center = Vec2::new(40.0, 60.0);
+
+        let inside: Vec<u32> = points.iter().filter(|(_, p)| p.distance(center) <= 15.0).map(|(id, _)| id).copied().collect();
+        let removed = tree.remove_in_circle(center, 15.0);
+
+        assert!(!inside.is_empty());
+        assert_eq!(removed, inside);
+        assert_eq!(tree.count(), points.len() - inside.len());
+        assert!(tree.all_points().iter().all(|(_, p)| p.distance(center) > 15.0));
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.remove_in_circle(center, 15.0).is_empty());
+    }
+
+    #[test]
+    fn activity_decays_over_frames() {
+        let mut activity = 1.0;
+        for _ in 0..60 {
+            let next = decayed_activity(activity, 3.0, 1.0 / 60.0);
+            assert!(next < activity);
+            activity = next;
+        }
+        assert!((activity - (-3.0f32).exp()).abs() < 1e-5);
+
+        let mut tree = split_tree();
+        assert!(tree.activity() > 0.0);
+        for _ in 0..600 {
+            tree.decay_activity(1.0 / 60.0);
+        }
+        assert!(tree.activity() < 1e-6);
+    }
+
+    #[test]
+    fn with_capacity_reserves_and_behaves_like_with_root() {
+        let mut reserved = QuadNode::with_capacity(region(), 4, 64);
+        assert!(reserved.points.capacity() >= 64);
+        assert!(reserved.weights.capacity() >= 64);
+        assert_eq!(reserved.count(), 0);
+
+        let points = scattered(100, 10);
+        for (id, position) in &points {
+            reserved.add(*id, position);
+        }
+        let plain = tree_of(&points, 4);
+
+        assert_eq!(reserved.node_count(), plain.node_count());
+        assert_eq!(sorted_ids(reserved.query(&Rect::new(10.0, 10.0, 30.0, 30.0))), sorted_ids(plain.query(&Rect::new(10.0, 10.0, 30.0, 30.0))));
+    }
+
+    #[test]
+    fn query_refs_matches_query() {
+        let tree = tree_of(&scattered(300, 11), 4);
+
+        for area in [region(), Rect::new(30.0, 5.0, 20.0, 60.0), Rect::new(-20.0, -20.0, 10.0, 10.0)] {
+            let refs: Vec<(u32, Vec2)> = tree.query_refs(&area).into_iter().copied().collect();
+            assert_eq!(refs, tree.query(&area));
+        }
+    }
+
+    #[test]
+    fn branch_two_matches_default() {
+        let points = scattered(200, 12);
+        let default = tree_of(&points, 4);
+        let mut explicit = QuadNode::with_root(region(), 4).with_branch(2);
+        for (id, position) in &points {
+            explicit.add(*id, position);
+        }
+
+        assert_eq!(explicit.node_count(), default.node_count());
+        assert_eq!(explicit.occupied_leaves(), default.occupied_leaves());
+        assert_eq!(explicit.leaf_occupancies(), default.leaf_occupancies());
+    }
+
+    #[test]
+    fn branch_four_tiles_region() {
+        let mut tree = QuadNode::with_root(region(), 1).with_branch(4);
+        tree.add(0, &Vec2::new(10.0, 10.0));
+        tree.add(1, &Vec2::new(90.0, 90.0));
+
+        let children: Vec<Rect> = tree.children().map(|child| child.region).collect();
+        assert_eq!(children.len(), 16);
+        for (i, child) in children.iter().enumerate() {
+            assert_eq!(*child, Rect::new((i % 4) as f32 * 25.0, (i / 4) as f32 * 25.0, 25.0, 25.0));
+        }
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn coincident_points_overflow_and_stay_queryable() {
+        let mut tree = QuadNode::with_root(region(), 2).with_max_depth(3);
+        for id in (0..50).rev() {
+            tree.add(id, &Vec2::new(30.0, 30.0));
+        }
+        tree.add(50, &Vec2::new(80.0, 80.0));
+
+        assert_eq!(tree.overflowing_leaves(), vec![Rect::new(25.0, 25.0, 12.5, 12.5)]);
+        assert_eq!(tree.validate(), Ok(()));
+
+        let found = tree.query_circle(Vec2::new(30.0, 30.0), 1.0);
+        assert_eq!(sorted_ids(found), (0..50).collect::<Vec<u32>>());
+
+        assert!(tree.remove(17, &Vec2::new(30.0, 30.0)));
+        assert_eq!(tree.query_circle(Vec2::new(30.0, 30.0), 1.0).len(), 49);
+    }
+
+    #[test]
+    fn fitted_root_contains_points_with_padding() {
+        let points = [(0, Vec2::new(120.0, 40.0)), (1, Vec2::new(300.0, -10.0)), (2, Vec2::new(180.0, 90.0))];
+        let tree = QuadNode::from_fitted(&points, 2);
+
+        assert_eq!(
+            tree.region,
+            Rect::new(120.0 - FIT_PADDING, -10.0 - FIT_PADDING, 180.0 + FIT_PADDING * 2.0, 100.0 + FIT_PADDING * 2.0)
+        );
+        assert!(points.iter().all(|(_, position)| tree.region.contains(*position)));
+        assert_eq!(tree.count(), points.len());
+    }
+
+    #[test]
+    fn densest_regions_known_layout_and_ties() {
+        let mut tree = QuadNode::with_root(region(), 3);
+        let points = [(10.0, 10.0), (20.0, 20.0), (30.0, 10.0), (60.0, 10.0), (70.0, 20.0), (10.0, 60.0), (20.0, 70.0), (60.0, 60.0)];
+        for (i, (x, y)) in points.into_iter().enumerate() {
+            tree.add(i as u32, &Vec2::new(x, y));
+        }
+
+        let top_left = Rect::new(0.0, 0.0, 50.0, 50.0);
+        let top_right = Rect::new(50.0, 0.0, 50.0, 50.0);
+        let bottom_left = Rect::new(0.0, 50.0, 50.0, 50.0);
+        let bottom_right = Rect::new(50.0, 50.0, 50.0, 50.0);
+
+        // The two 2-point leaves tie, the one met first in traversal order wins
+        assert_eq!(tree.densest_regions(2), vec![(top_left, 3), (top_right, 2)]);
+        assert_eq!(tree.densest_regions(3), vec![(top_left, 3), (top_right, 2), (bottom_left, 2)]);
+        assert_eq!(tree.densest_regions(10), vec![(top_left, 3), (top_right, 2), (bottom_left, 2), (bottom_right, 1)]);
+        assert!(tree.densest_regions(0).is_empty());
+    }
+
+    #[test]
+    fn query_by_leaf_groups_match_query() {
+        let tree = tree_of(&scattered(300, 13), 4);
+        let area = Rect::new(15.0, 35.0, 40.0, 30.0);
+
+        let groups = tree.query_by_leaf(&area);
+        assert!(groups.len() > 1);
+        for (leaf, points) in &groups {
+            assert!(!points.is_empty());
+            assert!(leaf.overlaps(&area));
+            assert!(points.iter().all(|(_, position)| leaf.contains(*position)));
+        }
+
+        let flattened: Vec<(u32, Vec2)> = groups.into_iter().flat_map(|(_, points)| points).collect();
+        assert_eq!(sorted_ids(flattened), sorted_ids(tree.query(&area)));
+    }
+
+    #[test]
+    fn metrics_pick_different_nearest() {
+        let origin = Vec2::new(50.0, 50.0);
+        let mut tree = QuadNode::with_root(region(), 1);
+        tree.add(0, &(origin + Vec2::new(3.0, 3.0)));
+        tree.add(1, &(origin + Vec2::new(-4.5, 0.0)));
+        tree.add(2, &(origin + Vec2::new(4.0, -1.0)));
+
+        // Euclidean 4.24 / 4.5 / 4.12, Manhattan 6 / 4.5 / 5, Chebyshev 3 / 4.5 / 4
+        assert_eq!(tree.nearest(origin, Metric::Euclidean).map(|(id, _)| id), Some(2));
+        assert_eq!(tree.nearest(origin, Metric::Manhattan).map(|(id, _)| id), Some(1));
+        assert_eq!(tree.nearest(origin, Metric::Chebyshev).map(|(id, _)| id), Some(0));
+
+        let ids: Vec<u32> = tree.k_nearest(origin, 3, Metric::Manhattan).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn count_in_matches_query_len() {
+        let mut tree = split_tree();
+        tree.add_circle(6, &Vec2::new(25.0, 25.0), 30.0);
+
+        let areas = [
+            region(),
+            Rect::new(0.0, 0.0, 40.0, 40.0),
+            Rect::new(45.0, 45.0, 10.0, 10.0),
+            Rect::new(60.0, 0.0, 40.0, 100.0),
+            Rect::new(200.0, 200.0, 10.0, 10.0),
+        ];
+        for area in &areas {
+            assert_eq!(tree.count_in(area), tree.query(area).len(), "{:?}", area);
+        }
+    }
+
+    #[test]
+    fn weighted_centroid_of_known_points() {
+        let area = Rect::new(0.0, 0.0, 500.0, 500.0);
+        let mut tree = QuadNode::with_root(area, 1);
+        tree.add_weighted(0, &Vec2::new(100.0, 100.0), 1.0);
+        tree.add_weighted(1, &Vec2::new(300.0, 200.0), 3.0);
+        tree.add_weighted(2, &Vec2::new(200.0, 400.0), 4.0);
+
+        let centroid = tree.weighted_centroid(&area).unwrap();
+        assert!((centroid - Vec2::new(225.0, 287.5)).length() < 1e-4, "{:?}", centroid);
+
+        assert_eq!(tree.weighted_centroid(&Rect::new(0.0, 0.0, 150.0, 150.0)), Some(Vec2::new(100.0, 100.0)));
+        assert_eq!(tree.weighted_centroid(&Rect::new(450.0, 450.0, 50.0, 50.0)), None);
+    }
+
+    #[test]
+    fn diff_reports_added_removed_and_moved() {
+        let before = tree_of(&[(0, Vec2::new(10.0, 10.0)), (1, Vec2::new(80.0, 10.0)), (2, Vec2::new(10.0, 80.0))], 1);
+        let after = tree_of(&[(0, Vec2::new(10.0, 10.0)), (1, Vec2::new(80.0, 80.0)), (3, Vec2::new(80.0, 10.0))], 1);
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.added, vec![3]);
+        assert_eq!(diff.removed, vec![2]);
+        assert_eq!(diff.moved, vec![1]);
+
+        assert_eq!(before.diff(&before), TreeDiff::default());
+    }
+
+    #[test]
+    fn with_root_finds_first_insert() {
+        let mut tree = QuadNode::with_root(region(), 4);
+        tree.add(0, &Vec2::new(30.0, 60.0));
+
+        assert_eq!(tree.query(&Rect::new(20.0, 50.0, 20.0, 20.0)), vec![(0, Vec2::new(30.0, 60.0))]);
+        assert_eq!(tree.query(&region()).len(), 1);
+    }
+
+    #[test]
+    fn min_node_size_stops_coincident_splits() {
+        let area = Rect::new(0.0, 0.0, 16.0, 16.0);
+        let mut tree = QuadNode::with_root(area, 2).with_min_node_size(4.0);
+        for id in 0..100 {
+            tree.add(id, &Vec2::new(1.0, 1.0));
+        }
+
+        // 16 -> 8 -> 4, halving again would go under the minimum
+        assert_eq!(tree.health().max_depth, 2);
+        assert_eq!(tree.count(), 100);
+        assert_eq!(sorted_ids(tree.query(&Rect::new(0.0, 0.0, 2.0, 2.0))), (0..100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn nearest_where_skips_rejected_ids() {
+        let tree = split_tree();
+        let position = Vec2::new(12.0, 12.0);
+
+        assert_eq!(tree.nearest_where(position, Metric::Euclidean, |_, _| true), tree.nearest(position, Metric::Euclidean));
+        assert_eq!(tree.nearest_where(position, Metric::Euclidean, |id, _| id != 0), Some((1, Vec2::new(20.0, 30.0))));
+        assert_eq!(tree.nearest_where(position, Metric::Euclidean, |_, _| false), None);
+        assert_eq!(tree.position_of(3), Some(Vec2::new(80.0, 80.0)));
+        assert_eq!(tree.position_of(9), None);
+    }
+}
@@ -0,0 +1,58 @@
+// Numbered frame paths sort correctly up to a million frames, e.g. `frames/frame_000042.png`
+pub fn frame_path(dir: &str, frame: u32) -> String {
+    format!("{}/frame_{:06}.png", dir, frame)
+}
+
+// Hands out one path per frame while active and stops by itself after `frames` frames
+pub struct FrameRecorder {
+    pub dir: String,
+    pub frames: u32,
+    pub active: bool,
+    frame: u32,
+}
+
+impl FrameRecorder {
+    pub fn new(dir: &str, frames: u32) -> Self {
+        Self {
+            dir: dir.to_string(),
+            frames,
+            active: false,
+            frame: 0,
+        }
+    }
+
+    pub fn start(&mut self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        self.frame = 0;
+        self.active = true;
+        Ok(())
+    }
+
+    pub fn next_path(&mut self) -> Option<String> {
+        if !self.active {
+            return None;
+        }
+
+        let path = frame_path(&self.dir, self.frame);
+        self.frame += 1;
+
+        if self.frame >= self.frames {
+            self.active = false;
+        }
+
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_path_zero_padding() {
+        assert_eq!(frame_path("frames", 0), "frames/frame_000000.png");
+        assert_eq!(frame_path("frames", 42), "frames/frame_000042.png");
+        assert_eq!(frame_path("out", 999_999), "out/frame_999999.png");
+        assert!(frame_path("frames", 9) < frame_path("frames", 10));
+    }
+}
@@ -0,0 +1,319 @@
+use macroquad::prelude::*;
+
+use crate::entity::{Bullet, Player, player_query_rect, time_to_impact};
+use crate::quadtree::QuadNode;
+use crate::{CIRCLE_MAX_SEGMENTS, CIRCLE_MIN_SEGMENTS, CIRCLE_SEGMENT_LENGTH};
+
+// Colors and line thickness used by the draw functions, `Default` is the demo's stock look.
+// Entities with a higher `z` are drawn later, on top of the lower ones
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderStyle {
+    pub node_color: Color,
+    pub node_thickness: f32,
+    pub player_color: Color,
+    pub bullet_color: Color,
+    pub player_z: i32,
+    pub bullet_z: i32,
+}
+
+impl Default for RenderStyle {
+    fn default() -> Self {
+        Self {
+            node_color: GREEN,
+            node_thickness: 1.0,
+            player_color: RED,
+            bullet_color: WHITE,
+            player_z: 1,
+            bullet_z: 0,
+        }
+    }
+}
+
+pub trait DrawShape {
+    fn draw(&self, _style: &RenderStyle) {}
+}
+
+// Stable, so entries sharing a `z` keep the order they were pushed in
+pub fn sort_by_z<T>(layers: &mut [(i32, T)]) {
+    layers.sort_by_key(|(z, _)| *z);
+}
+
+// Keeps every edge around `CIRCLE_SEGMENT_LENGTH` long so big circles stay smooth
+pub fn circle_segments(radius: f32) -> u8 {
+    let segments = (2.0 * std::f32::consts::PI * radius / CIRCLE_SEGMENT_LENGTH).ceil();
+    segments.clamp(CIRCLE_MIN_SEGMENTS as f32, CIRCLE_MAX_SEGMENTS as f32) as u8
+}
+
+pub fn draw_smooth_circle(x: f32, y: f32, radius: f32, color: Color) {
+    draw_poly(x, y, circle_segments(radius), radius, 0.0, color);
+}
+
+impl DrawShape for Player {
+    fn draw(&self, style: &RenderStyle) {
+        draw_smooth_circle(self.entity.position.x, self.entity.position.y, self.entity.bouding_box.r, style.player_color);
+    }
+}
+
+impl DrawShape for Bullet {
+    fn draw(&self, style: &RenderStyle) {
+        draw_smooth_circle(self.entity.position.x, self.entity.position.y, self.entity.bouding_box.r, style.bullet_color);
+    }
+}
+
+impl DrawShape for QuadNode {
+    fn draw(&self, style: &RenderStyle) {
+        let r = self.region;
+        draw_rectangle_lines(r.x, r.y, r.w, r.h, style.node_thickness, style.node_color);
+
+        for region in self.children() {
+            region.draw(style);
+        }
+    }
+}
+
+// Lines to the predicted contact point of the `count` bullets closest to hitting the player
+pub fn draw_impact_predictions(player: &Player, bullets: &[Bullet], count: usize) {
+    let mut threats: Vec<(&Bullet, f32)> = bullets.iter()
+        .filter_map(|bullet| time_to_impact(bullet, player).map(|t| (bullet, t)))
+        .collect();
+    threats.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    for (bullet, t) in threats.into_iter().take(count) {
+        let from = bullet.entity.position;
+        let contact = from + bullet.velocity * t;
+
+        draw_line(from.x, from.y, contact.x, contact.y, 1.0, ORANGE);
+        draw_text(&format!("{:.2}", t), contact.x, contact.y, 16.0, ORANGE);
+    }
+}
+
+// Tip of a bullet's velocity line, `scale` seconds of travel ahead of `pos`
+pub fn velocity_line_end(pos: Vec2, vel: Vec2, scale: f32) -> Vec2 {
+    pos + vel * scale
+}
+
+pub fn draw_velocity_lines(bullets: &[Bullet], scale: f32) {
+    for bullet in bullets {
+        let from = bullet.entity.position;
+        let to = velocity_line_end(from, bullet.velocity, scale);
+
+        // A resting bullet has no direction to show
+        if to != from {
+            draw_line(from.x, from.y, to.x, to.y, 1.0, SKYBLUE);
+        }
+    }
+}
+
+// Candidates returned for the query rect that the tight circle would have rejected
+pub fn rect_only_candidates(rect_candidates: &[(u32, Vec2)], circle_hits: &[(u32, Vec2)]) -> Vec<(u32, Vec2)> {
+    rect_candidates.iter()
+        .filter(|(id, _)| !circle_hits.iter().any(|(hit, _)| hit == id))
+        .copied()
+        .collect()
+}
+
+// The player's broad phase rect, its actual circle and the points only the rect picked up
+pub fn draw_query_coverage(player: &Player, tree: &QuadNode) {
+    let rect = player_query_rect(player);
+    let center = player.entity.position;
+    let radius = player.entity.bouding_box.r;
+
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, SKYBLUE);
+    draw_circle_lines(center.x, center.y, radius, 1.0, PINK);
+
+    let candidates = tree.query(&rect);
+    let hits = tree.query_circle(center, radius);
+    for (_, position) in rect_only_candidates(&candidates, &hits) {
+        draw_circle_lines(position.x, position.y, 3.0, 1.0, ORANGE);
+    }
+}
+
+// One line from the player center to every broad phase candidate
+pub fn candidate_lines(player: Vec2, candidates: &[(u32, Vec2)]) -> Vec<(Vec2, Vec2)> {
+    candidates.iter().map(|(_, position)| (player, *position)).collect()
+}
+
+pub fn draw_candidate_lines(player: Vec2, candidates: &[(u32, Vec2)]) {
+    for (from, to) in candidate_lines(player, candidates) {
+        draw_line(from.x, from.y, to.x, to.y, 1.0, Color::new(1.0, 1.0, 0.0, 0.3));
+    }
+}
+
+// Transparent at no activity up to a solid highlight at full activity
+pub fn activity_color(activity: f32) -> Color {
+    Color::new(1.0, 0.6, 0.0, activity.clamp(0.0, 1.0) * 0.5)
+}
+
+// Fills every node with its activity glow, busy parts of the tree light up and fade out
+pub fn draw_activity(node: &QuadNode) {
+    if node.activity() > 0.01 {
+        let r = node.region;
+        draw_rectangle(r.x, r.y, r.w, r.h, activity_color(node.activity()));
+    }
+
+    for region in node.children() {
+        draw_activity(region);
+    }
+}
+
+// Only outlines leaves that hold at least one point
+pub fn draw_occupied(node: &QuadNode, style: &RenderStyle) {
+    for r in node.occupied_leaves() {
+        draw_rectangle_lines(r.x, r.y, r.w, r.h, style.node_thickness, style.node_color);
+    }
+}
+
+// A node whose smaller side covers fewer than `threshold_px` pixels at `zoom` is too small to draw
+pub fn below_lod_threshold(node_size: f32, zoom: f32, threshold_px: f32) -> bool {
+    node_size * zoom < threshold_px
+}
+
+// Draws `node` like `DrawShape::draw`, but stops descending once the children get too small to see
+pub fn draw_tree_lod(node: &QuadNode, zoom: f32, threshold_px: f32, style: &RenderStyle) {
+    let r = node.region;
+    draw_rectangle_lines(r.x, r.y, r.w, r.h, style.node_thickness, style.node_color);
+
+    for region in node.children() {
+        if !below_lod_threshold(region.region.w.min(region.region.h), zoom, threshold_px) {
+            draw_tree_lod(region, zoom, threshold_px, style);
+        }
+    }
+}
+
+// Multiples of `spacing` that fall within `[start, end]`
+fn gridline_positions(spacing: f32, start: f32, end: f32) -> Vec<f32> {
+    let first = (start / spacing).ceil() as i32;
+    let last = (end / spacing).floor() as i32;
+
+    (first..=last).map(|i| i as f32 * spacing).collect()
+}
+
+// x coordinates of the vertical lines and y coordinates of the horizontal lines
+pub fn gridlines(spacing: f32, bounds: Rect) -> (Vec<f32>, Vec<f32>) {
+    (
+        gridline_positions(spacing, bounds.left(), bounds.right()),
+        gridline_positions(spacing, bounds.top(), bounds.bottom()),
+    )
+}
+
+pub fn draw_reference_grid(spacing: f32, bounds: Rect) {
+    let (xs, ys) = gridlines(spacing, bounds);
+
+    for x in xs {
+        draw_line(x, bounds.top(), x, bounds.bottom(), 1.0, DARKGRAY);
+    }
+
+    for y in ys {
+        draw_line(bounds.left(), y, bounds.right(), y, 1.0, DARKGRAY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lod_threshold_at_several_zooms() {
+        // A 16 unit node is 16 px at zoom 1, 1.6 px at zoom 0.1 and 160 px at zoom 10
+        assert!(!below_lod_threshold(16.0, 1.0, 4.0));
+        assert!(below_lod_threshold(16.0, 0.1, 4.0));
+        assert!(!below_lod_threshold(16.0, 10.0, 4.0));
+        assert!(!below_lod_threshold(16.0, 0.25, 4.0));
+        assert!(below_lod_threshold(16.0, 0.2, 4.0));
+        assert!(below_lod_threshold(2.0, 1.0, 4.0));
+    }
+
+    #[test]
+    fn gridlines_count_and_positions() {
+        let (xs, ys) = gridlines(100.0, Rect::new(0.0, 0.0, 1000.0, 600.0));
+        assert_eq!(xs.len(), 11);
+        assert_eq!(ys, vec![0.0, 100.0, 200.0, 300.0, 400.0, 500.0, 600.0]);
+
+        // Bounds off the spacing only get the multiples inside them
+        let (xs, ys) = gridlines(50.0, Rect::new(-30.0, 20.0, 140.0, 60.0));
+        assert_eq!(xs, vec![0.0, 50.0, 100.0]);
+        assert_eq!(ys, vec![50.0]);
+    }
+
+    #[test]
+    fn circle_segments_monotonic_and_clamped() {
+        assert_eq!(circle_segments(0.0), CIRCLE_MIN_SEGMENTS);
+        assert_eq!(circle_segments(1.0), CIRCLE_MIN_SEGMENTS);
+        assert_eq!(circle_segments(10_000.0), CIRCLE_MAX_SEGMENTS);
+
+        let mut previous = 0;
+        for step in 0..400 {
+            let segments = circle_segments(step as f32 * 0.5);
+            assert!(segments >= previous);
+            assert!((CIRCLE_MIN_SEGMENTS..=CIRCLE_MAX_SEGMENTS).contains(&segments));
+            previous = segments;
+        }
+    }
+
+    #[test]
+    fn default_style_keeps_stock_look() {
+        let style = RenderStyle::default();
+        assert_eq!(style.node_color, GREEN);
+        assert_eq!(style.node_thickness, 1.0);
+        assert_eq!(style.player_color, RED);
+        assert_eq!(style.bullet_color, WHITE);
+        assert_eq!((style.player_z, style.bullet_z), (1, 0));
+    }
+
+    #[test]
+    fn rect_only_candidates_set_difference() {
+        let rect = [(1, Vec2::new(1.0, 1.0)), (2, Vec2::new(2.0, 2.0)), (3, Vec2::new(3.0, 3.0)), (4, Vec2::new(4.0, 4.0))];
+        let circle = [(3, Vec2::new(3.0, 3.0)), (1, Vec2::new(1.0, 1.0))];
+
+        assert_eq!(rect_only_candidates(&rect, &circle), vec![(2, Vec2::new(2.0, 2.0)), (4, Vec2::new(4.0, 4.0))]);
+        assert_eq!(rect_only_candidates(&rect, &[]), rect.to_vec());
+        assert!(rect_only_candidates(&rect, &rect).is_empty());
+    }
+
+    #[test]
+    fn activity_color_alpha_follows_activity() {
+        assert_eq!(activity_color(0.0).a, 0.0);
+        assert_eq!(activity_color(0.5).a, 0.25);
+        assert_eq!(activity_color(1.0).a, 0.5);
+        assert_eq!(activity_color(3.0).a, 0.5);
+        assert!(activity_color(0.2).a < activity_color(0.4).a);
+    }
+
+    #[test]
+    fn velocity_line_end_zero_and_diagonal() {
+        let position = Vec2::new(10.0, 20.0);
+        assert_eq!(velocity_line_end(position, Vec2::ZERO, 0.1), position);
+        assert_eq!(velocity_line_end(position, Vec2::new(100.0, -100.0), 0.1), Vec2::new(20.0, 10.0));
+    }
+
+    #[test]
+    fn sort_by_z_keeps_ties_in_order() {
+        let mut layers = vec![(1, "player"), (0, "bullet a"), (2, "overlay"), (0, "bullet b"), (1, "stuck"), (0, "bullet c")];
+        sort_by_z(&mut layers);
+
+        assert_eq!(
+            layers,
+            vec![(0, "bullet a"), (0, "bullet b"), (0, "bullet c"), (1, "player"), (1, "stuck"), (2, "overlay")]
+        );
+    }
+
+    #[test]
+    fn one_candidate_line_per_candidate() {
+        let mut tree = QuadNode::with_root(Rect::new(0.0, 0.0, 100.0, 100.0), 2);
+        for id in 0..10 {
+            tree.add(id, &Vec2::new(5.0 + id as f32 * 9.0, 5.0 + id as f32 * 4.0));
+        }
+
+        let player = Vec2::new(20.0, 20.0);
+        let candidates = tree.query(&Rect::new(0.0, 0.0, 40.0, 40.0));
+        let lines = candidate_lines(player, &candidates);
+
+        assert!(!candidates.is_empty());
+        assert_eq!(lines.len(), candidates.len());
+        for ((from, to), (_, position)) in lines.iter().zip(&candidates) {
+            assert_eq!(*from, player);
+            assert_eq!(to, position);
+        }
+        assert!(candidate_lines(player, &[]).is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+use macroquad::math::Vec2;
+
+use crate::config::Config;
+use crate::entity::{Bullet, Movable};
+use crate::world::World;
+use crate::{BULLET_RADIUS, FRAME_CAP_DT, PLAYER_RADIUS, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// Hand placed setup for reproducing collisions, spawning is disabled so the only bullets in
+// the run are the ones added here
+pub struct Scenario {
+    player_position: Vec2,
+    player_radius: f32,
+    bullets: Vec<Bullet>,
+    tpf: f32,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self {
+            player_position: Vec2::new(WINDOW_WIDTH as f32 / 2.0, WINDOW_HEIGHT as f32 / 2.0),
+            player_radius: PLAYER_RADIUS,
+            bullets: Vec::new(),
+            tpf: FRAME_CAP_DT as f32,
+        }
+    }
+
+    pub fn player_at(mut self, position: Vec2) -> Self {
+        self.player_position = position;
+        self
+    }
+
+    pub fn player_radius(mut self, radius: f32) -> Self {
+        self.player_radius = radius;
+        self
+    }
+
+    pub fn bullet(mut self, position: Vec2, velocity: Vec2) -> Self {
+        self.bullets.push(Bullet::with_velocity(position, BULLET_RADIUS, velocity));
+        self
+    }
+
+    pub fn frame_time(mut self, tpf: f32) -> Self {
+        self.tpf = tpf;
+        self
+    }
+
+    // Builds the world and steps it `steps` times with a fixed frame time
+    pub fn run(self, steps: usize) -> World {
+        let mut world = World::new(&Config::default());
+        world.spawner.set_enabled(false);
+
+        world.player.set_radius(self.player_radius);
+        world.player.entity.set_position(self.player_position);
+        world.bullets = self.bullets;
+
+        for _ in 0..steps {
+            world.step(self.tpf);
+        }
+
+        world
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn head_on_bounce() {
+        let world = Scenario::new()
+            .player_at(Vec2::new(500.0, 300.0))
+            .player_radius(50.0)
+            .bullet(Vec2::new(600.0, 300.0), Vec2::new(-200.0, 0.0))
+            .run(60);
+
+        assert_eq!(world.bullets.len(), 1);
+        assert!(world.stats.hits >= 1);
+
+        let bullet = &world.bullets[0];
+        assert!(bullet.velocity.x > 0.0, "bullet still heading in at {:?}", bullet.velocity);
+        assert!(bullet.velocity.y.abs() < 1e-3);
+        assert!(bullet.entity.position.x > 551.0);
+    }
+}
@@ -0,0 +1,362 @@
+use std::f32::consts::FRAC_PI_3;
+
+use macroquad::math::{Rect, Vec2};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::entity::Bullet;
+use crate::quadtree::{Metric, QuadNode};
+use crate::{BULLET_RADIUS, EDGE_SPAWN_OFFSET, SPAWN_MAX_RETRIES, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// Spawns a bullet just outside a random window edge, heading inward with up to 60 degrees of spread
+pub fn spawn_edge_bullet(seed: u64, radius: f32) -> Bullet {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let width = WINDOW_WIDTH as f32;
+    let height = WINDOW_HEIGHT as f32;
+
+    let (position, inward) = match rng.gen_range(0..4) {
+        0 => (Vec2::new(rng.gen_range(0.0..width), -EDGE_SPAWN_OFFSET), Vec2::new(0.0, 1.0)),
+        1 => (Vec2::new(rng.gen_range(0.0..width), height + EDGE_SPAWN_OFFSET), Vec2::new(0.0, -1.0)),
+        2 => (Vec2::new(-EDGE_SPAWN_OFFSET, rng.gen_range(0.0..height)), Vec2::new(1.0, 0.0)),
+        _ => (Vec2::new(width + EDGE_SPAWN_OFFSET, rng.gen_range(0.0..height)), Vec2::new(-1.0, 0.0)),
+    };
+
+    let angle = rng.gen_range(-FRAC_PI_3..FRAC_PI_3);
+    let speed = rng.gen_range(100.0..300.0);
+    let velocity = Vec2::new(
+        inward.x * angle.cos() - inward.y * angle.sin(),
+        inward.x * angle.sin() + inward.y * angle.cos(),
+    ) * speed;
+
+    Bullet::with_velocity(position, radius, velocity)
+}
+
+pub fn is_in_play_area(position: Vec2) -> bool {
+    Rect::new(
+        -EDGE_SPAWN_OFFSET,
+        -EDGE_SPAWN_OFFSET,
+        WINDOW_WIDTH as f32 + EDGE_SPAWN_OFFSET * 2.0,
+        WINDOW_HEIGHT as f32 + EDGE_SPAWN_OFFSET * 2.0,
+    ).contains(position)
+}
+
+// Uniform in `start..start + length`, a zero length span skips the draw and returns `start`
+fn sample_span(rng: &mut StdRng, start: f32, length: f32) -> f32 {
+    if length <= 0.0 {
+        return start;
+    }
+
+    rng.gen_range(start..start + length)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SpawnMode {
+    Top,
+    Edges,
+}
+
+// `is_active` is the per interval latch flipped by `spawn`/`reset`, `enabled` is the user's
+// switch and keeps `reset` from re-arming the spawner while off
+pub struct BulletSpawner {
+    pub is_active: bool,
+    pub enabled: bool,
+    pub mode: SpawnMode,
+    // Minimum distance between a new bullet and any other, 0 disables the check
+    pub min_spacing: f32,
+    // Area `SpawnMode::Top` bullets start in, a zero height rect spawns along a line
+    pub spawn_region: Rect,
+    // Bounds every bullet radius is drawn from, the upper one exclusive, equal bounds give a constant radius
+    pub radius_range: (f32, f32),
+    rng: StdRng,
+}
+
+impl Default for BulletSpawner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BulletSpawner {
+    pub fn new() -> Self {
+        Self::with_seed(macroquad::rand::rand() as u64)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            is_active: true,
+            enabled: true,
+            mode: SpawnMode::Top,
+            min_spacing: 0.0,
+            spawn_region: Rect::new(0.0, 0.0, WINDOW_WIDTH as f32, 0.0),
+            radius_range: (BULLET_RADIUS, BULLET_RADIUS),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            SpawnMode::Top => SpawnMode::Edges,
+            SpawnMode::Edges => SpawnMode::Top,
+        };
+    }
+
+    fn candidate(&mut self) -> Bullet {
+        let rng = &mut self.rng;
+        let radius = sample_span(rng, self.radius_range.0, self.radius_range.1 - self.radius_range.0);
+
+        match self.mode {
+            SpawnMode::Top => Bullet::new(
+                Vec2::new(
+                    sample_span(rng, self.spawn_region.x, self.spawn_region.w),
+                    sample_span(rng, self.spawn_region.y, self.spawn_region.h)),
+                radius,
+                rng.gen_range(100.0..300.0)),
+            SpawnMode::Edges => spawn_edge_bullet(rng.gen(), radius),
+        }
+    }
+
+    // With `min_spacing` set, candidates closer than that to a bullet in `existing` or earlier in
+    // the batch are redrawn up to `SPAWN_MAX_RETRIES` times and dropped after that
+    pub fn spawn(&mut self, no_bullets: i32, existing: &QuadNode) -> Option<Vec<Bullet>> {
+        if !self.is_active {
+            return None
+        }
+
+        let mut bullets: Vec<Bullet> = Vec::new();
+
+        for _ in 0..no_bullets {
+            for _ in 0..=SPAWN_MAX_RETRIES {
+                let bullet = self.candidate();
+                let position = bullet.entity.position;
+
+                let crowded = self.min_spacing > 0.0 && (
+                    existing.any_in_circle(position, self.min_spacing, |_, _| true).is_some()
+                        || bullets.iter().any(|b| b.entity.position.distance(position) < self.min_spacing)
+                );
+
+                if !crowded {
+                    bullets.push(bullet);
+                    break;
+                }
+            }
+        }
+
+        self.is_active = false;
+
+        Some(bullets)
+    }
+
+    // Number of bullets for the next burst, uniform in the inclusive `range`
+    pub fn burst_size(&mut self, range: (i32, i32)) -> i32 {
+        self.rng.gen_range(range.0..=range.1)
+    }
+
+    pub fn reset(&mut self) {
+        self.is_active = self.enabled;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.is_active = enabled;
+    }
+}
+
+// Adds a stationary bullet to both the scene and the tree, returning its id
+pub fn place_bullet(bullets: &mut Vec<Bullet>, tree: &mut QuadNode, position: Vec2) -> u32 {
+    let id = bullets.len() as u32;
+
+    bullets.push(Bullet::with_velocity(position, BULLET_RADIUS, Vec2::ZERO));
+    tree.add(id, &position);
+
+    id
+}
+
+// Removes the bullet closest to `position`, the last bullet takes over its id. Stale ids left in
+// the tree by despawns since the last rebuild are skipped, as `try_hit` does
+pub fn remove_nearest_bullet(bullets: &mut Vec<Bullet>, tree: &mut QuadNode, position: Vec2) -> Option<Bullet> {
+    let last = bullets.len().checked_sub(1)? as u32;
+    let (id, point) = tree.nearest_where(position, Metric::Euclidean, |id, _| id <= last)?;
+
+    tree.remove(id, &point);
+    if id != last {
+        // The tree may lag behind the live positions, so the moved entry is looked up where it was stored
+        if let Some(moved) = tree.position_of(last) {
+            tree.remove(last, &moved);
+            tree.add(id, &moved);
+        }
+    }
+
+    Some(bullets.swap_remove(id as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> QuadNode {
+        QuadNode::with_root(Rect::new(0.0, 0.0, 100.0, 100.0), 4)
+    }
+
+    #[test]
+    fn edge_bullets_start_outside_heading_in() {
+        let window = Rect::new(0.0, 0.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32);
+
+        for seed in 0..200 {
+            let bullet = spawn_edge_bullet(seed, BULLET_RADIUS);
+            let position = bullet.entity.position;
+            assert!(!window.contains(position), "seed {} spawned inside at {:?}", seed, position);
+
+            let inward = if position.y < 0.0 {
+                Vec2::new(0.0, 1.0)
+            } else if position.y > window.h {
+                Vec2::new(0.0, -1.0)
+            } else if position.x < 0.0 {
+                Vec2::new(1.0, 0.0)
+            } else {
+                Vec2::new(-1.0, 0.0)
+            };
+            assert!(bullet.velocity.dot(inward) > 0.0, "seed {} heads away from the window", seed);
+        }
+    }
+
+    #[test]
+    fn disabled_spawner_never_spawns() {
+        let tree = tree();
+        let mut spawner = BulletSpawner::with_seed(1);
+        spawner.set_enabled(false);
+
+        for _ in 0..5 {
+            assert!(spawner.spawn(10, &tree).is_none());
+            spawner.reset();
+        }
+
+        spawner.set_enabled(true);
+        assert_eq!(spawner.spawn(10, &tree).map(|bullets| bullets.len()), Some(10));
+    }
+
+    #[test]
+    fn spawns_keep_min_spacing() {
+        let mut existing = QuadNode::with_root(Rect::new(0.0, 0.0, 200.0, 200.0), 4);
+        existing.add(0, &Vec2::new(100.0, 100.0));
+
+        let mut spawner = BulletSpawner::with_seed(2);
+        spawner.min_spacing = 20.0;
+        spawner.spawn_region = Rect::new(0.0, 0.0, 200.0, 200.0);
+        let bullets = spawner.spawn(40, &existing).unwrap();
+        assert!(!bullets.is_empty());
+
+        for (i, bullet) in bullets.iter().enumerate() {
+            let position = bullet.entity.position;
+            assert!(position.distance(Vec2::new(100.0, 100.0)) >= 20.0);
+
+            for other in &bullets[i + 1..] {
+                assert!(position.distance(other.entity.position) >= 20.0);
+            }
+        }
+    }
+
+    #[test]
+    fn spawns_inside_spawn_region() {
+        let mut spawner = BulletSpawner::with_seed(4);
+        spawner.spawn_region = Rect::new(100.0, 50.0, 200.0, 30.0);
+        let bullets = spawner.spawn(500, &tree()).unwrap();
+
+        assert_eq!(bullets.len(), 500);
+        assert!(bullets.iter().all(|bullet| spawner.spawn_region.contains(bullet.entity.position)));
+    }
+
+    #[test]
+    fn radius_within_range() {
+        let mut spawner = BulletSpawner::with_seed(3);
+        spawner.radius_range = (2.0, 5.0);
+
+        for _ in 0..1000 {
+            let radius = spawner.candidate().entity.bouding_box.r;
+            assert!((2.0..5.0).contains(&radius), "radius {} out of range", radius);
+        }
+    }
+
+    #[test]
+    fn radius_zero_width_range() {
+        let mut spawner = BulletSpawner::with_seed(3);
+        spawner.radius_range = (4.0, 4.0);
+
+        for _ in 0..100 {
+            assert_eq!(spawner.candidate().entity.bouding_box.r, 4.0);
+        }
+    }
+
+    #[test]
+    fn place_then_remove_nearest() {
+        let mut bullets = Vec::new();
+        let mut tree = tree();
+
+        assert_eq!(place_bullet(&mut bullets, &mut tree, Vec2::new(10.0, 10.0)), 0);
+        assert_eq!(place_bullet(&mut bullets, &mut tree, Vec2::new(50.0, 50.0)), 1);
+        assert_eq!(place_bullet(&mut bullets, &mut tree, Vec2::new(90.0, 90.0)), 2);
+
+        let removed = remove_nearest_bullet(&mut bullets, &mut tree, Vec2::new(12.0, 12.0)).unwrap();
+        assert_eq!(removed.entity.position, Vec2::new(10.0, 10.0));
+
+        // The last bullet took over id 0, both in the scene and in the tree
+        assert_eq!(bullets.len(), 2);
+        assert_eq!(bullets[0].entity.position, Vec2::new(90.0, 90.0));
+        let mut stored = tree.all_points();
+        stored.sort_by_key(|(id, _)| *id);
+        assert_eq!(stored, vec![(0, Vec2::new(90.0, 90.0)), (1, Vec2::new(50.0, 50.0))]);
+    }
+
+    #[test]
+    fn remove_from_empty_scene() {
+        let mut bullets = Vec::new();
+        let mut tree = tree();
+        assert!(remove_nearest_bullet(&mut bullets, &mut tree, Vec2::ZERO).is_none());
+
+        // A stale entry with no bullet behind it is not removable either
+        tree.add(0, &Vec2::new(10.0, 10.0));
+        assert!(remove_nearest_bullet(&mut bullets, &mut tree, Vec2::ZERO).is_none());
+    }
+
+    #[test]
+    fn remove_skips_stale_ids() {
+        let mut bullets = Vec::new();
+        let mut tree = tree();
+        place_bullet(&mut bullets, &mut tree, Vec2::new(50.0, 50.0));
+        tree.add(7, &Vec2::new(1.0, 1.0));
+
+        let removed = remove_nearest_bullet(&mut bullets, &mut tree, Vec2::ZERO).unwrap();
+        assert_eq!(removed.entity.position, Vec2::new(50.0, 50.0));
+        assert!(bullets.is_empty());
+    }
+
+    #[test]
+    fn remove_uses_stored_position_of_moved_bullet() {
+        let mut bullets = Vec::new();
+        let mut tree = tree();
+        place_bullet(&mut bullets, &mut tree, Vec2::new(10.0, 10.0));
+        place_bullet(&mut bullets, &mut tree, Vec2::new(90.0, 90.0));
+
+        // The last bullet drifted since the tree was built
+        bullets[1].entity.position = Vec2::new(60.0, 60.0);
+        remove_nearest_bullet(&mut bullets, &mut tree, Vec2::ZERO);
+
+        assert_eq!(tree.all_points(), vec![(0, Vec2::new(90.0, 90.0))]);
+    }
+
+    #[test]
+    fn burst_sizes_stay_in_range_around_midpoint() {
+        let mut spawner = BulletSpawner::with_seed(7);
+        let range = (80, 120);
+        let bursts = 10_000;
+
+        let mut total = 0;
+        for _ in 0..bursts {
+            let size = spawner.burst_size(range);
+            assert!((range.0..=range.1).contains(&size), "burst of {}", size);
+            total += size;
+        }
+
+        let mean = total as f32 / bursts as f32;
+        assert!((mean - 100.0).abs() < 1.0, "mean {}", mean);
+        assert_eq!(BulletSpawner::with_seed(7).burst_size(range), BulletSpawner::with_seed(7).burst_size(range));
+    }
+}
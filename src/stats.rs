@@ -0,0 +1,132 @@
+#[derive(Default)]
+pub struct Stats {
+    pub bullets_spawned: usize,
+    pub hits: usize,
+    pub damage: f32,
+    pub peak_bullets: usize,
+    pub frames: u64,
+    pub total_frame_time: f64,
+    // Seconds spent in the player broad phase query, one entry per frame
+    pub query_times: Vec<f64>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_spawn(&mut self, count: usize) {
+        self.bullets_spawned += count;
+    }
+
+    pub fn record_hits(&mut self, count: usize) {
+        self.hits += count;
+    }
+
+    pub fn record_damage(&mut self, damage: f32) {
+        self.damage += damage;
+    }
+
+    pub fn record_frame(&mut self, bullets_in_scene: usize, frame_time: f64) {
+        self.peak_bullets = self.peak_bullets.max(bullets_in_scene);
+        self.frames += 1;
+        self.total_frame_time += frame_time;
+    }
+
+    pub fn record_query_time(&mut self, seconds: f64) {
+        self.query_times.push(seconds);
+    }
+
+    pub fn average_frame_time(&self) -> f64 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+
+        self.total_frame_time / self.frames as f64
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "bullets spawned: {}\nhits: {}\ndamage taken: {:.1}\npeak bullets: {}\naverage frame time: {:.3} ms",
+            self.bullets_spawned,
+            self.hits,
+            self.damage,
+            self.peak_bullets,
+            self.average_frame_time() * 1000.0,
+        )
+    }
+
+    pub fn latency_summary(&self) -> String {
+        let mut sorted = self.query_times.clone();
+        sorted.sort_by(f64::total_cmp);
+
+        format!(
+            "query latency p50: {:.3} ms\nquery latency p95: {:.3} ms\nquery latency p99: {:.3} ms",
+            percentile(&sorted, 50.0) * 1000.0,
+            percentile(&sorted, 95.0) * 1000.0,
+            percentile(&sorted, 99.0) * 1000.0,
+        )
+    }
+}
+
+// `p` in 0..=100, linearly interpolated between the two closest ranks, zero for no samples
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+// Fraction of the `total` bullets the broad phase handed to the narrow phase, zero for an empty scene
+pub fn candidate_ratio(candidates: usize, total: usize) -> f32 {
+    if total == 0 {
+        return 0.0;
+    }
+
+    candidates as f32 / total as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_format() {
+        let mut stats = Stats::new();
+        stats.record_spawn(120);
+        stats.record_hits(3);
+        stats.record_damage(12.5);
+        stats.record_frame(80, 0.010);
+        stats.record_frame(95, 0.020);
+
+        assert_eq!(
+            stats.summary(),
+            "bullets spawned: 120\nhits: 3\ndamage taken: 12.5\npeak bullets: 95\naverage frame time: 15.000 ms"
+        );
+    }
+
+    #[test]
+    fn percentile_known_inputs() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 50.0), 3.0);
+        assert_eq!(percentile(&sorted, 100.0), 5.0);
+        assert_eq!(percentile(&sorted, 25.0), 2.0);
+        assert!((percentile(&sorted, 90.0) - 4.6).abs() < 1e-12);
+        assert_eq!(percentile(&[7.0], 95.0), 7.0);
+        assert_eq!(percentile(&[], 50.0), 0.0);
+    }
+
+    #[test]
+    fn candidate_ratio_known_values() {
+        assert_eq!(candidate_ratio(25, 100), 0.25);
+        assert_eq!(candidate_ratio(100, 100), 1.0);
+        assert_eq!(candidate_ratio(0, 100), 0.0);
+        assert_eq!(candidate_ratio(0, 0), 0.0);
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+pub struct FrameLimiter {
+    pub target_dt: f64,
+    pub enabled: bool,
+}
+
+impl FrameLimiter {
+    pub fn new(target_dt: f64) -> Self {
+        Self { target_dt, enabled: false }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    // Time left to sleep so the frame lasts `target_dt`, zero if the frame already ran over
+    pub fn wait(&self, last_frame_time: f64) -> Duration {
+        Duration::from_secs_f64((self.target_dt - last_frame_time).max(0.0))
+    }
+}
+
+// Exponential moving average, `alpha` in [0, 1] is how much weight the new `sample` gets
+pub fn ema(prev: f32, sample: f32, alpha: f32) -> f32 {
+    prev + (sample - prev) * alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_under_target() {
+        let limiter = FrameLimiter::new(1.0 / 60.0);
+        let wait = limiter.wait(0.01);
+        assert!((wait.as_secs_f64() - (1.0 / 60.0 - 0.01)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn wait_over_target() {
+        let limiter = FrameLimiter::new(1.0 / 60.0);
+        assert_eq!(limiter.wait(0.05), Duration::ZERO);
+        assert_eq!(limiter.wait(1.0 / 60.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn ema_converges_to_constant_input() {
+        let mut value = 0.0;
+        for _ in 0..200 {
+            value = ema(value, 16.0, 0.1);
+        }
+        assert!((value - 16.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn ema_step_response() {
+        // One step covers `alpha` of the gap, the next one `alpha` of what's left
+        let first = ema(10.0, 20.0, 0.25);
+        assert_eq!(first, 12.5);
+        assert_eq!(ema(first, 20.0, 0.25), 14.375);
+
+        assert_eq!(ema(10.0, 20.0, 0.0), 10.0);
+        assert_eq!(ema(10.0, 20.0, 1.0), 20.0);
+    }
+}
@@ -0,0 +1,861 @@
+use std::time::Instant;
+
+use macroquad::math::{Rect, Vec2};
+
+use crate::config::Config;
+use crate::entity::{
+    Bullet, ControlMode, Movable, Physics, Player, StuckBullet, attraction, bounce_off_walls, circle_overlap_area, resolve_hit,
+    player_query_rect, separation_vector, try_hit,
+};
+use crate::quadtree::QuadNode;
+use crate::spawner::{BulletSpawner, is_in_play_area};
+use crate::stats::Stats;
+use crate::timing::ema;
+use crate::{
+    BULLET_SPAWN_DELAY, DAMAGE_PER_AREA, FRAGMENT_COUNT, FRAME_CAP_DT, FRAME_TIME_SMOOTHING, FULL_REBUILD_RATIO, MAGNET_RADIUS,
+    MAGNET_STRENGTH, MAX_STUCK_BULLETS, PLAYER_RADIUS, QUADTREE_MAX_REGION_LIMIT, QUADTREE_MIN_NODE_SIZE, QUADTREE_MIN_REGION_LIMIT,
+    QUADTREE_REGION_LIMIT, REBUILD_INTERVAL, REINSERT_THRESHOLD, WINDOW_HEIGHT, WINDOW_WIDTH,
+};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum BroadPhaseMode {
+    Point,
+    Circle,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum HitResponse {
+    Bounce,
+    Destroy,
+    Fragment,
+    Stick,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DespawnReason {
+    Destroyed,
+    OutOfBounds,
+    Expired,
+}
+
+// Audio cue for a single player hit, picked from the response it got
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SoundEvent {
+    Hit,
+    Bounce,
+    Destroy,
+}
+
+impl From<HitResponse> for SoundEvent {
+    fn from(response: HitResponse) -> Self {
+        match response {
+            HitResponse::Bounce => SoundEvent::Bounce,
+            HitResponse::Destroy | HitResponse::Fragment => SoundEvent::Destroy,
+            HitResponse::Stick => SoundEvent::Hit,
+        }
+    }
+}
+
+// Emitted for every bullet leaving the scene, `position` is where it was last seen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DespawnEvent {
+    pub reason: DespawnReason,
+    pub position: Vec2,
+}
+
+// Hits found by the radius aware broad phase that the point only one did not report
+pub fn missed_hits(point_hits: &[usize], circle_hits: &[usize]) -> Vec<usize> {
+    circle_hits.iter()
+        .filter(|id| !point_hits.contains(id))
+        .copied()
+        .collect()
+}
+
+// With the player pinned, a step at `input` velocity scrolls the world the opposite way instead
+pub fn world_offset_for_player_move(input: Vec2, dt: f32) -> Vec2 {
+    -input * dt
+}
+
+// Past `ratio` of the bullets moving, patching the tree one bullet at a time costs more than rebuilding it
+pub fn should_full_rebuild(moved: usize, total: usize, ratio: f32) -> bool {
+    total > 0 && moved as f32 / total as f32 > ratio
+}
+
+// True when both lists hold the same bullet ids, ignoring order and duplicates
+pub fn compare_hits(quadtree: &[usize], brute: &[usize]) -> bool {
+    let mut quadtree = quadtree.to_vec();
+    let mut brute = brute.to_vec();
+
+    quadtree.sort_unstable();
+    quadtree.dedup();
+    brute.sort_unstable();
+    brute.dedup();
+
+    quadtree == brute
+}
+
+// Maps `position` back into `bounds` as if opposite edges were glued together
+pub fn wrap_position(position: Vec2, bounds: Rect) -> Vec2 {
+    Vec2::new(
+        bounds.x + (position.x - bounds.x).rem_euclid(bounds.w),
+        bounds.y + (position.y - bounds.y).rem_euclid(bounds.h),
+    )
+}
+
+// Translations of `area` by whole world sizes that still overlap `bounds`, always starting
+// with the untranslated one
+pub fn wrap_offsets(area: &Rect, bounds: &Rect) -> Vec<Vec2> {
+    let mut offsets = Vec::new();
+
+    for dy in [0.0, -bounds.h, bounds.h] {
+        for dx in [0.0, -bounds.w, bounds.w] {
+            let shifted = area.offset(Vec2::new(dx, dy));
+            if (dx == 0.0 && dy == 0.0) || shifted.overlaps(bounds) {
+                offsets.push(Vec2::new(dx, dy));
+            }
+        }
+    }
+
+    offsets
+}
+
+// Simulation state without any window or input, `main` drives it one frame at a time
+pub struct World {
+    pub player: Player,
+    pub bullets: Vec<Bullet>,
+    pub spawner: BulletSpawner,
+    pub tree: QuadNode,
+    pub stats: Stats,
+    pub physics: Physics,
+    pub spawn_timer: f64,
+    // Moving average of the frame time driving `spawn_timer`, a single long frame barely moves it
+    pub smoothed_tpf: f32,
+    pub magnetism: bool,
+    pub manual_placement: bool,
+    // Cap on `bullets` plus `stuck`, 0 means no cap
+    pub max_bullets: usize,
+    pub spawn_count_range: (i32, i32),
+    // Toroidal world, entities leaving through an edge come back through the opposite one
+    pub wrap: bool,
+    // Bullets bounce off the tree bounds instead of leaving, ignored while wrapping
+    pub walls: bool,
+    // Keyboard mode keeps the player at the center of the tree bounds and moves the bullets instead
+    pub pin_player: bool,
+    // Checks the quadtree hits against an O(n) scan every frame, only in `Circle` mode since
+    // the point broad phase is knowingly approximate
+    pub verify_hits: bool,
+    pub broad_phase: BroadPhaseMode,
+    pub hit_response: HitResponse,
+    pub score: u32,
+    // Bullets attached to the player in `Stick` mode, no longer part of `bullets` or the tree
+    pub stuck: Vec<StuckBullet>,
+    // Bullets removed during the last `step`, cleared at the start of the next one
+    pub despawns: Vec<DespawnEvent>,
+    // One entry per player hit during the last `step`, for the caller to play, cleared like `despawns`
+    pub sounds: Vec<SoundEvent>,
+    // Bullets returned by the player broad phase query, at their tree positions, and actual
+    // hits during the last `step`
+    pub frame_candidates: Vec<(u32, Vec2)>,
+    pub frame_hits: usize,
+    // Bullets whose center ended up in a different leaf than at the previous refresh
+    pub leaf_migrations: usize,
+    // Filled in `Circle` mode with the hits a point only broad phase would have missed
+    pub missed_hits: Vec<usize>,
+    // Full rebuild every `rebuild_interval` frames, in between only bullets that moved
+    // further than `reinsert_threshold` from `inserted_positions` get reinserted, unless
+    // more than `full_rebuild_ratio` of them did
+    pub rebuild_interval: u32,
+    pub reinsert_threshold: f32,
+    pub full_rebuild_ratio: f32,
+    frames_since_rebuild: u32,
+    inserted_positions: Vec<Vec2>,
+}
+
+impl World {
+    pub fn new(config: &Config) -> Self {
+        let region = Rect::new(0.0, 0.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32);
+        let screen_middle = Vec2::new(WINDOW_WIDTH as f32 / 2.0, WINDOW_HEIGHT as f32 / 2.0);
+
+        let tree = QuadNode::with_root(region, QUADTREE_REGION_LIMIT).with_min_node_size(QUADTREE_MIN_NODE_SIZE);
+
+        let mut world = Self {
+            player: Player::new(PLAYER_RADIUS, screen_middle),
+            bullets: Vec::new(),
+            spawner: BulletSpawner::with_seed(config.seed),
+            tree,
+            stats: Stats::new(),
+            physics: Physics::default(),
+            spawn_timer: 0.0,
+            smoothed_tpf: FRAME_CAP_DT as f32,
+            magnetism: false,
+            manual_placement: false,
+            max_bullets: config.max_bullets,
+            spawn_count_range: config.spawn_count_range,
+            wrap: false,
+            walls: false,
+            pin_player: false,
+            verify_hits: false,
+            broad_phase: BroadPhaseMode::Point,
+            hit_response: HitResponse::Bounce,
+            score: 0,
+            stuck: Vec::new(),
+            despawns: Vec::new(),
+            sounds: Vec::new(),
+            frame_candidates: Vec::new(),
+            frame_hits: 0,
+            leaf_migrations: 0,
+            missed_hits: Vec::new(),
+            rebuild_interval: REBUILD_INTERVAL,
+            reinsert_threshold: REINSERT_THRESHOLD,
+            full_rebuild_ratio: FULL_REBUILD_RATIO,
+            frames_since_rebuild: 0,
+            inserted_positions: Vec::new(),
+        };
+
+        world.spawner.min_spacing = config.min_spacing;
+        world.spawner.radius_range = config.radius_range;
+
+        if config.initial_bullets > 0 {
+            let count = world.spawn_room(config.initial_bullets);
+            if let Some(mut bullets) = world.spawner.spawn(count as i32, &world.tree) {
+                world.stats.record_spawn(bullets.len());
+                world.bullets.append(&mut bullets);
+            }
+            world.spawner.reset();
+        }
+
+        world
+    }
+
+    // How many of `requested` new bullets fit under `max_bullets`
+    pub fn spawn_room(&self, requested: usize) -> usize {
+        if self.max_bullets == 0 {
+            return requested;
+        }
+
+        let in_scene = self.bullets.len() + self.stuck.len();
+        requested.min(self.max_bullets.saturating_sub(in_scene))
+    }
+
+    pub fn rebuild_tree(&mut self) {
+        self.tree.clear();
+
+        for (i, bullet) in self.bullets.iter().enumerate() {
+            match self.broad_phase {
+                BroadPhaseMode::Point => self.tree.add(i as u32, &bullet.entity.position),
+                BroadPhaseMode::Circle => self.tree.add_circle(i as u32, &bullet.entity.position, bullet.entity.bouding_box.r),
+            }
+        }
+
+        self.inserted_positions = self.bullets.iter().map(|b| b.entity.position).collect();
+        self.frames_since_rebuild = 0;
+
+        debug_assert_eq!(self.tree.validate(), Ok(()));
+    }
+
+    // Ids are scene indices, so any change in the bullet count forces a full rebuild, as does
+    // circle storage since a moved circle may span a different set of leaves
+    pub fn refresh_tree(&mut self) {
+        self.frames_since_rebuild += 1;
+
+        if self.frames_since_rebuild >= self.rebuild_interval
+            || self.inserted_positions.len() != self.bullets.len()
+            || self.broad_phase == BroadPhaseMode::Circle
+        {
+            self.rebuild_tree();
+            return;
+        }
+
+        let moved: Vec<usize> = (0..self.bullets.len())
+            .filter(|&i| self.inserted_positions[i].distance(self.bullets[i].entity.position) > self.reinsert_threshold)
+            .collect();
+
+        if should_full_rebuild(moved.len(), self.bullets.len(), self.full_rebuild_ratio) {
+            self.rebuild_tree();
+            return;
+        }
+
+        for i in moved {
+            let position = self.bullets[i].entity.position;
+            // Bullets that were outside the root when inserted aren't in the tree to move yet
+            if !self.tree.update_position(i as u32, &self.inserted_positions[i], &position) {
+                self.tree.add(i as u32, &position);
+            }
+            self.inserted_positions[i] = position;
+        }
+
+        debug_assert_eq!(self.tree.validate(), Ok(()));
+    }
+
+    fn track_leaf_migrations(&mut self) {
+        self.leaf_migrations = 0;
+
+        for bullet in &mut self.bullets {
+            let leaf = self.tree.leaf_region(bullet.entity.position);
+
+            if bullet.leaf.is_some() && bullet.leaf != leaf {
+                self.leaf_migrations += 1;
+            }
+            bullet.leaf = leaf;
+        }
+    }
+
+    fn point_only_hits(&self, player_rect: &Rect) -> Vec<usize> {
+        let mut tree = QuadNode::with_root(self.tree.region, self.tree.limit());
+
+        for (i, bullet) in self.bullets.iter().enumerate() {
+            tree.add(i as u32, &bullet.entity.position);
+        }
+
+        let ids = tree.query(player_rect).iter().map(|p| p.0).collect();
+        try_hit(&self.player, &self.bullets, ids, self.physics.contact_epsilon).unwrap_or_default()
+    }
+
+    // Changes the leaf limit and rebuilds right away so the new subdivision shows up this frame
+    pub fn set_region_limit(&mut self, limit: usize) {
+        self.tree.set_limit(limit.clamp(QUADTREE_MIN_REGION_LIMIT, QUADTREE_MAX_REGION_LIMIT));
+        self.rebuild_tree();
+    }
+
+    pub fn toggle_broad_phase(&mut self) {
+        self.broad_phase = match self.broad_phase {
+            BroadPhaseMode::Point => BroadPhaseMode::Circle,
+            BroadPhaseMode::Circle => BroadPhaseMode::Point,
+        };
+        self.rebuild_tree();
+    }
+
+    pub fn toggle_hit_response(&mut self) {
+        self.hit_response = match self.hit_response {
+            HitResponse::Bounce => HitResponse::Destroy,
+            HitResponse::Destroy => HitResponse::Fragment,
+            HitResponse::Fragment => HitResponse::Stick,
+            HitResponse::Stick => HitResponse::Bounce,
+        };
+
+        // Released bullets rejoin the scene where they hang and get knocked off by the next response
+        self.bullets.extend(self.stuck.drain(..).map(|stuck| stuck.bullet));
+    }
+
+    // `ids` must be sorted, the remaining bullets keep their order
+    fn take_bullets(&mut self, ids: &[usize]) -> Vec<Bullet> {
+        let (taken, kept) = std::mem::take(&mut self.bullets)
+            .into_iter()
+            .enumerate()
+            .partition::<Vec<_>, _>(|(i, _)| ids.binary_search(i).is_ok());
+
+        self.bullets = kept.into_iter().map(|(_, bullet)| bullet).collect();
+        self.missed_hits.clear();
+
+        taken.into_iter().map(|(_, bullet)| bullet).collect()
+    }
+
+    fn destroy_bullets(&mut self, ids: &[usize]) {
+        for bullet in self.take_bullets(ids) {
+            self.despawns.push(DespawnEvent { reason: DespawnReason::Destroyed, position: bullet.entity.position });
+        }
+
+        self.score += ids.len() as u32;
+    }
+
+    // Player query rect grown by the reinsertion threshold to cover positions lagging in the tree
+    fn player_query_area(&self) -> Rect {
+        let rect = player_query_rect(&self.player);
+        let margin = if self.rebuild_interval > 1 { self.reinsert_threshold } else { 0.0 };
+
+        Rect::new(rect.x - margin, rect.y - margin, rect.w + margin * 2.0, rect.h + margin * 2.0)
+    }
+
+    // Handle collisition player-bullets, if a bullet gets hit bounce it back
+    fn collide(&mut self) {
+        let player_rect = self.player_query_area();
+        let query_start = Instant::now();
+        let mut candidates = self.tree.query_current(&player_rect);
+        self.stats.record_query_time(query_start.elapsed().as_secs_f64());
+        candidates.sort_unstable_by_key(|p| p.0);
+        candidates.dedup_by_key(|p| p.0);
+        let ids = candidates.iter().map(|p| p.0).collect();
+        self.frame_candidates.extend(candidates);
+        let player_has_hit = try_hit(&self.player, &self.bullets, ids, self.physics.contact_epsilon);
+        self.frame_hits += player_has_hit.as_ref().map_or(0, Vec::len);
+
+        if self.verify_hits && self.broad_phase == BroadPhaseMode::Circle {
+            // Bullets entirely outside the root were never inserted, the tree can't be expected to find them
+            let mut stored_ids: Vec<u32> = self.tree.all_points().into_iter().map(|(id, _)| id).collect();
+            stored_ids.sort_unstable();
+            stored_ids.dedup();
+            let brute = try_hit(&self.player, &self.bullets, stored_ids, self.physics.contact_epsilon).unwrap_or_default();
+            let matches = compare_hits(player_has_hit.as_deref().unwrap_or_default(), &brute);
+
+            if !matches {
+                eprintln!("warning: quadtree hits {:?} differ from brute force hits {:?}", player_has_hit, brute);
+            }
+            debug_assert!(matches, "broad phase missed or invented hits");
+        }
+
+        if self.broad_phase == BroadPhaseMode::Circle {
+            let point_hits = self.point_only_hits(&player_rect);
+            self.missed_hits.extend(missed_hits(&point_hits, player_has_hit.as_deref().unwrap_or_default()));
+        }
+
+        if let Some(hit_ids) = player_has_hit {
+            self.stats.record_hits(hit_ids.len());
+            self.sounds.extend(std::iter::repeat_n(SoundEvent::from(self.hit_response), hit_ids.len()));
+
+            for &hit_id in &hit_ids {
+                let overlap = circle_overlap_area(&self.bullets[hit_id].entity.bouding_box, &self.player.entity.bouding_box);
+                self.stats.record_damage(overlap * DAMAGE_PER_AREA);
+            }
+
+            match self.hit_response {
+                HitResponse::Bounce => {
+                    if self.player.control == ControlMode::Keyboard {
+                        let push = separation_vector(&self.player, &hit_ids, &self.bullets);
+                        let position = self.player.entity.position + push;
+                        self.player.entity.set_position(position);
+                    }
+
+                    for hit_id in hit_ids {
+                        let recoil = resolve_hit(&mut self.bullets[hit_id], &self.player, &self.physics);
+
+                        if self.player.control == ControlMode::Keyboard {
+                            self.player.recoil += recoil;
+                        }
+                    }
+                }
+                HitResponse::Destroy => self.destroy_bullets(&hit_ids),
+                // Fragments themselves are destroyed on hit, otherwise a bullet stuck to the
+                // player would multiply every frame
+                HitResponse::Fragment => {
+                    let mut fragments: Vec<Bullet> = hit_ids.iter()
+                        .map(|&hit_id| &self.bullets[hit_id])
+                        .filter(|bullet| bullet.lifetime.is_none())
+                        .flat_map(|bullet| bullet.fragment(FRAGMENT_COUNT))
+                        .collect();
+
+                    self.destroy_bullets(&hit_ids);
+                    fragments.truncate(self.spawn_room(fragments.len()));
+                    self.bullets.extend(fragments);
+                }
+                // Once the player is full the remaining hits pass through untouched
+                HitResponse::Stick => {
+                    let room = MAX_STUCK_BULLETS.saturating_sub(self.stuck.len());
+                    let attached: Vec<usize> = hit_ids.into_iter().take(room).collect();
+
+                    for bullet in self.take_bullets(&attached) {
+                        self.stuck.push(StuckBullet::attach(bullet, &self.player));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn step(&mut self, tpf: f32) {
+        self.despawns.clear();
+        self.sounds.clear();
+        self.frame_candidates.clear();
+        self.frame_hits = 0;
+
+        // Only drawn while a burst is due so the seeded sequence doesn't depend on the frame rate
+        if !self.manual_placement && self.spawner.is_active {
+            let burst = self.spawner.burst_size(self.spawn_count_range);
+            let count = self.spawn_room(burst as usize);
+            if let Some(mut bullets) = self.spawner.spawn(count as i32, &self.tree) {
+                self.stats.record_spawn(bullets.len());
+                self.bullets.append(&mut bullets);
+            }
+        }
+
+        self.refresh_tree();
+        self.tree.decay_activity(tpf);
+        self.track_leaf_migrations();
+
+        self.missed_hits.clear();
+
+        // With wrapping on the player also collides as a ghost on the opposite side of every
+        // edge its query area crosses
+        let offsets = if self.wrap {
+            wrap_offsets(&self.player_query_area(), &self.tree.region)
+        } else {
+            vec![Vec2::ZERO]
+        };
+
+        for offset in offsets {
+            if self.inserted_positions.len() != self.bullets.len() {
+                self.rebuild_tree();
+            }
+
+            let position = self.player.entity.position;
+            self.player.entity.set_position(position + offset);
+            self.collide();
+            let position = self.player.entity.position;
+            self.player.entity.set_position(position - offset);
+        }
+
+        if self.magnetism {
+            let pulls: Vec<Vec2> = self.bullets.iter()
+                .map(|bullet| {
+                    let position = bullet.entity.position;
+                    // Each neighbour pulls once, however many leaves it was found in
+                    let mut neighbours = self.tree.query_circle(position, MAGNET_RADIUS);
+                    neighbours.sort_unstable_by_key(|(id, _)| *id);
+                    neighbours.dedup_by_key(|(id, _)| *id);
+
+                    neighbours.iter()
+                        .fold(Vec2::ZERO, |pull, (_, other)| {
+                            pull + attraction(position, *other, MAGNET_STRENGTH, MAGNET_RADIUS)
+                        })
+                })
+                .collect();
+
+            for (bullet, pull) in self.bullets.iter_mut().zip(pulls) {
+                bullet.velocity += pull * tpf;
+            }
+        }
+
+        for bullet in &mut self.bullets {
+            bullet.update(tpf, &self.physics);
+        }
+
+        if self.bullets.iter().any(Bullet::is_expired) {
+            let despawns = &mut self.despawns;
+            self.bullets.retain(|b| {
+                if b.is_expired() {
+                    despawns.push(DespawnEvent { reason: DespawnReason::Expired, position: b.entity.position });
+                }
+                !b.is_expired()
+            });
+            self.missed_hits.clear();
+        }
+
+        if self.player.control == ControlMode::Keyboard {
+            self.player.integrate(tpf);
+            self.player.apply_recoil(tpf, &self.physics);
+
+            // Only the player's own motion scrolls the world, pushes from hits are dropped
+            if self.pin_player {
+                let offset = world_offset_for_player_move(self.player.velocity, tpf);
+                for bullet in &mut self.bullets {
+                    let position = bullet.entity.position + offset;
+                    bullet.entity.set_position(position);
+                }
+
+                let bounds = self.tree.region;
+                self.player.entity.set_position(Vec2::new(bounds.x + bounds.w / 2.0, bounds.y + bounds.h / 2.0));
+            }
+        }
+
+        if self.walls && !self.wrap {
+            for bullet in &mut self.bullets {
+                bounce_off_walls(bullet, &self.tree.region, self.physics.wall_restitution);
+            }
+        }
+
+        if self.wrap {
+            let bounds = self.tree.region;
+
+            for bullet in &mut self.bullets {
+                bullet.entity.set_position(wrap_position(bullet.entity.position, bounds));
+            }
+
+            let position = wrap_position(self.player.entity.position, bounds);
+            self.player.entity.set_position(position);
+        }
+
+        for stuck in &mut self.stuck {
+            stuck.follow(self.player.entity.position);
+        }
+
+        self.stats.record_frame(self.bullets.len(), tpf as f64);
+
+        self.smoothed_tpf = ema(self.smoothed_tpf, tpf, FRAME_TIME_SMOOTHING);
+        self.spawn_timer += self.smoothed_tpf as f64;
+        if self.spawn_timer > BULLET_SPAWN_DELAY {
+            self.spawn_timer = 0.0;
+            self.spawner.reset();
+
+            let despawns = &mut self.despawns;
+            let wrap = self.wrap;
+            self.bullets.retain(|b| {
+                let in_play = wrap || is_in_play_area(b.entity.position);
+                if !in_play {
+                    despawns.push(DespawnEvent { reason: DespawnReason::OutOfBounds, position: b.entity.position });
+                }
+                in_play
+            });
+            self.missed_hits.clear();
+        }
+    }
+
+    // Hashes the player and every bullet in scene order, stable across runs, platforms and
+    // toolchains with the same seed since it only folds in the bit patterns (64 bit FNV-1a)
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut hash_vec = |v: Vec2| {
+            for byte in v.x.to_bits().to_le_bytes().into_iter().chain(v.y.to_bits().to_le_bytes()) {
+                hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+            }
+        };
+
+        hash_vec(self.player.entity.position);
+        for bullet in &self.bullets {
+            hash_vec(bullet.entity.position);
+            hash_vec(bullet.velocity);
+        }
+
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_hits_ignores_order_and_duplicates() {
+        assert!(compare_hits(&[3, 1, 2], &[1, 2, 3]));
+        assert!(compare_hits(&[1, 1, 2], &[2, 1]));
+        assert!(compare_hits(&[], &[]));
+    }
+
+    #[test]
+    fn compare_hits_reports_missing_and_extra() {
+        assert!(!compare_hits(&[1, 2], &[1, 2, 3]));
+        assert!(!compare_hits(&[1, 2, 3], &[1, 2]));
+        assert!(!compare_hits(&[1, 4], &[1, 2]));
+    }
+
+    #[test]
+    fn verify_hits_ignores_bullets_outside_root() {
+        let mut world = World::new(&Config::default());
+        world.broad_phase = BroadPhaseMode::Circle;
+        world.verify_hits = true;
+        world.player.entity.set_position(Vec2::new(50.0, 300.0));
+
+        // Overlaps the player but lies entirely left of the root, so it's never inserted
+        world.bullets = vec![Bullet::with_velocity(Vec2::new(-5.0, 300.0), 1.0, Vec2::ZERO)];
+        world.rebuild_tree();
+        world.collide();
+
+        assert_eq!(world.frame_hits, 0);
+    }
+
+    fn stepped_world(seed: u64) -> World {
+        let config = Config { seed, initial_bullets: 50, ..Config::default() };
+        let mut world = World::new(&config);
+
+        for _ in 0..30 {
+            world.step(1.0 / 60.0);
+        }
+
+        world
+    }
+
+    #[test]
+    fn state_hash_same_seed() {
+        assert_eq!(stepped_world(7).state_hash(), stepped_world(7).state_hash());
+        assert_ne!(stepped_world(7).state_hash(), stepped_world(8).state_hash());
+    }
+
+    #[test]
+    fn state_hash_sees_perturbed_bullet() {
+        let world = stepped_world(7);
+        let mut perturbed = stepped_world(7);
+        assert!(!perturbed.bullets.is_empty());
+
+        let position = perturbed.bullets[0].entity.position;
+        perturbed.bullets[0].entity.set_position(position + Vec2::new(0.001, 0.0));
+        assert_ne!(world.state_hash(), perturbed.state_hash());
+    }
+
+    #[test]
+    fn slow_bullets_found_between_rebuilds() {
+        let mut world = World::new(&Config::default());
+        world.rebuild_interval = 1000;
+        // Never enough movers in one frame to trigger a full rebuild instead of reinserting
+        world.full_rebuild_ratio = 1.0;
+        world.bullets = vec![
+            Bullet::with_velocity(Vec2::new(200.0, 200.0), 1.0, Vec2::new(30.0, 10.0)),
+            Bullet::with_velocity(Vec2::new(700.0, 400.0), 1.0, Vec2::new(-5.0, 0.0)),
+        ];
+        world.rebuild_tree();
+
+        for _ in 0..120 {
+            for bullet in &mut world.bullets {
+                bullet.update(1.0 / 60.0, &world.physics);
+            }
+            world.refresh_tree();
+
+            let margin = world.reinsert_threshold;
+            for (id, bullet) in world.bullets.iter().enumerate() {
+                let position = bullet.entity.position;
+                let area = Rect::new(position.x - margin, position.y - margin, margin * 2.0, margin * 2.0);
+                assert!(world.tree.query(&area).iter().any(|(other, _)| *other == id as u32));
+            }
+        }
+
+        assert_eq!(world.frames_since_rebuild, 120);
+    }
+
+    #[test]
+    fn missed_hits_lists_circle_only_hits() {
+        assert_eq!(missed_hits(&[1, 3], &[1, 2, 3, 4]), vec![2, 4]);
+        assert!(missed_hits(&[1, 2], &[1, 2]).is_empty());
+    }
+
+    #[test]
+    fn point_broad_phase_misses_straddling_bullet() {
+        let mut world = World::new(&Config::default());
+        world.broad_phase = BroadPhaseMode::Circle;
+
+        // Small bullets packed right of the player split the tree until the leaf holding the
+        // big bullet's center starts past x = 625, clear of the player's 400..600 query rect
+        world.bullets = (0..30)
+            .map(|i| Bullet::with_velocity(Vec2::new(650.0 + (i % 10) as f32 * 9.0, 310.0 + (i / 10) as f32 * 20.0), 1.0, Vec2::ZERO))
+            .collect();
+        world.bullets.push(Bullet::with_velocity(Vec2::new(640.0, 300.0), 50.0, Vec2::ZERO));
+        world.rebuild_tree();
+        world.collide();
+
+        assert_eq!(world.missed_hits, vec![30]);
+    }
+
+    #[test]
+    fn initial_bullets_seeded_before_step() {
+        let world = World::new(&Config { initial_bullets: 50, ..Config::default() });
+        assert_eq!(world.bullets.len(), 50);
+
+        assert!(World::new(&Config::default()).bullets.is_empty());
+    }
+
+    #[test]
+    fn smaller_region_limit_more_nodes() {
+        let mut world = World::new(&Config { initial_bullets: 300, ..Config::default() });
+        world.set_region_limit(20);
+        let coarse = world.tree.node_count();
+
+        world.set_region_limit(2);
+        assert!(world.tree.node_count() > coarse, "{} vs {}", world.tree.node_count(), coarse);
+        assert_eq!(world.tree.count(), 300);
+    }
+
+    fn scene_around_player(world: &mut World) {
+        world.bullets = vec![
+            Bullet::with_velocity(Vec2::new(550.0, 300.0), 2.0, Vec2::ZERO),
+            Bullet::with_velocity(Vec2::new(100.0, 100.0), 2.0, Vec2::ZERO),
+            Bullet::with_velocity(Vec2::new(500.0, 360.0), 2.0, Vec2::ZERO),
+        ];
+        world.rebuild_tree();
+    }
+
+    #[test]
+    fn destroy_removes_hit_bullets() {
+        let mut world = World::new(&Config::default());
+        world.hit_response = HitResponse::Destroy;
+        scene_around_player(&mut world);
+        world.collide();
+
+        assert_eq!(world.bullets.len(), 1);
+        assert_eq!(world.bullets[0].entity.position, Vec2::new(100.0, 100.0));
+
+        let mut destroyed: Vec<Vec2> = world.despawns.iter()
+            .filter(|event| event.reason == DespawnReason::Destroyed)
+            .map(|event| event.position)
+            .collect();
+        destroyed.sort_by(|a, b| a.x.total_cmp(&b.x));
+        assert_eq!(destroyed, vec![Vec2::new(500.0, 360.0), Vec2::new(550.0, 300.0)]);
+        assert_eq!(world.despawns.len(), 2);
+    }
+
+    #[test]
+    fn wrap_position_each_edge_and_corner() {
+        let bounds = Rect::new(0.0, 0.0, 1000.0, 600.0);
+        assert_eq!(wrap_position(Vec2::new(-10.0, 300.0), bounds), Vec2::new(990.0, 300.0));
+        assert_eq!(wrap_position(Vec2::new(1010.0, 300.0), bounds), Vec2::new(10.0, 300.0));
+        assert_eq!(wrap_position(Vec2::new(500.0, -20.0), bounds), Vec2::new(500.0, 580.0));
+        assert_eq!(wrap_position(Vec2::new(500.0, 620.0), bounds), Vec2::new(500.0, 20.0));
+        assert_eq!(wrap_position(Vec2::new(-5.0, 605.0), bounds), Vec2::new(995.0, 5.0));
+        assert_eq!(wrap_position(Vec2::new(500.0, 300.0), bounds), Vec2::new(500.0, 300.0));
+    }
+
+    #[test]
+    fn max_bullets_caps_spawning() {
+        let config = Config { max_bullets: 100, spawn_count_range: (200, 200), ..Config::default() };
+        let mut world = World::new(&config);
+
+        let mut peak = 0;
+        for _ in 0..120 {
+            world.step(1.0 / 60.0);
+            assert!(world.bullets.len() + world.stuck.len() <= 100);
+            peak = peak.max(world.bullets.len());
+        }
+        assert_eq!(peak, 100);
+    }
+
+    #[test]
+    fn crossing_a_boundary_counts_one_migration() {
+        let mut world = World::new(&Config::default());
+        world.spawner.set_enabled(false);
+
+        // Enough resting bullets to split the root into quadrants meeting at x = 500
+        world.bullets = (0..12)
+            .map(|i| Bullet::with_velocity(Vec2::new(100.0 + (i % 4) as f32 * 250.0, 50.0 + (i / 4) as f32 * 250.0), 1.0, Vec2::ZERO))
+            .collect();
+        world.bullets.push(Bullet::with_velocity(Vec2::new(495.0, 100.0), 1.0, Vec2::new(600.0, 0.0)));
+
+        world.step(1.0 / 60.0);
+        assert_eq!(world.leaf_migrations, 0);
+        world.step(1.0 / 60.0);
+        assert_eq!(world.leaf_migrations, 1);
+        world.step(1.0 / 60.0);
+        assert_eq!(world.leaf_migrations, 0);
+    }
+
+    #[test]
+    fn full_rebuild_past_ratio() {
+        assert!(!should_full_rebuild(0, 100, 0.5));
+        assert!(!should_full_rebuild(30, 100, 0.5));
+        assert!(!should_full_rebuild(50, 100, 0.5));
+        assert!(should_full_rebuild(51, 100, 0.5));
+        assert!(should_full_rebuild(100, 100, 0.5));
+        assert!(should_full_rebuild(11, 100, 0.1));
+        assert!(!should_full_rebuild(0, 0, 0.5));
+    }
+
+    #[test]
+    fn world_offset_inverts_player_move() {
+        assert_eq!(world_offset_for_player_move(Vec2::new(100.0, 0.0), 0.5), Vec2::new(-50.0, 0.0));
+        assert_eq!(world_offset_for_player_move(Vec2::new(-40.0, 80.0), 0.25), Vec2::new(10.0, -20.0));
+        assert_eq!(world_offset_for_player_move(Vec2::ZERO, 1.0), Vec2::ZERO);
+
+        // Scrolling the world by the offset keeps the player's position relative to it the same as moving
+        let player = Vec2::new(500.0, 300.0);
+        let bullet = Vec2::new(650.0, 200.0);
+        let input = Vec2::new(30.0, -60.0);
+        let moved = (bullet - (player + input * 0.1)).length();
+        let scrolled = (bullet + world_offset_for_player_move(input, 0.1) - player).length();
+        assert!((moved - scrolled).abs() < 1e-3);
+    }
+
+    #[test]
+    fn one_sound_per_hit_for_each_response() {
+        let expected = [
+            (HitResponse::Bounce, SoundEvent::Bounce),
+            (HitResponse::Destroy, SoundEvent::Destroy),
+            (HitResponse::Fragment, SoundEvent::Destroy),
+            (HitResponse::Stick, SoundEvent::Hit),
+        ];
+
+        for (response, sound) in expected {
+            let mut world = World::new(&Config::default());
+            world.hit_response = response;
+            scene_around_player(&mut world);
+            world.collide();
+
+            assert_eq!(world.sounds, vec![sound; 2]);
+        }
+    }
+}